@@ -0,0 +1,254 @@
+// 外部コマンドの引数構築機能
+//
+// このモジュールは、解決済みの接続パラメータからssh/scp/rsyncの
+// コマンドライン引数（argv）を組み立てます。argvの先頭要素はプログラム名です。
+
+use crate::error::{Error, Result};
+use crate::resolve::{Endpoint, Location};
+use std::process::Command;
+
+/// SSH接続用のargvを構築します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+///
+/// # 戻り値
+/// `ssh -p <port> [-i <key>] <connection>` 形式のargv
+pub fn ssh_argv(endpoint: &Endpoint) -> Vec<String> {
+    let mut argv = vec!["ssh".to_string()];
+    argv.extend(ssh_options(endpoint, "-p"));
+    argv.push(endpoint.connection.clone());
+    argv
+}
+
+/// リモートでコマンドを実行するSSHのargvを構築します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `command` - リモートシェルで実行するコマンド文字列
+///
+/// # 戻り値
+/// `ssh -p <port> [-i <key>] <connection> <command>` 形式のargv
+pub fn ssh_exec_argv(endpoint: &Endpoint, command: &str) -> Vec<String> {
+    let mut argv = ssh_argv(endpoint);
+    argv.push(command.to_string());
+    argv
+}
+
+/// SCP転送用のargvを構築します
+///
+/// ポート番号と秘密鍵は、コピー元がリモートの場合は最初のコピー元の、
+/// そうでない場合はコピー先の接続先から取得します。
+///
+/// # 引数
+/// * `sources` - コピー元（1つ以上）
+/// * `dst` - コピー先
+///
+/// # 戻り値
+/// `scp -r [-P <port>] [-i <key>] [-C] [-c <cipher>] [-l <limit>] <src>... <dst>` 形式のargv
+pub fn scp_argv(sources: &[Location], dst: &Location) -> Vec<String> {
+    let mut argv = vec!["scp".to_string(), "-r".to_string()];
+    if let Some(endpoint) = transfer_endpoint(sources, dst) {
+        argv.extend(ssh_options(endpoint, "-P"));
+        // scpの帯域制限はKbit/s単位
+        if let Some(limit) = endpoint.bwlimit {
+            argv.push("-l".to_string());
+            argv.push((u64::from(limit) * 8192 / 1000).to_string());
+        }
+    }
+    // コピー元のglobはリモートで展開させ、コピー先はリテラルとして扱う
+    argv.extend(sources.iter().map(Location::to_escaped_arg));
+    argv.push(dst.to_literal_arg());
+    argv
+}
+
+/// rsync転送用のargvを構築します
+///
+/// rsyncはリモート間の直接転送をサポートしないため、
+/// 両端がリモートの場合はエラーを返します。
+/// rsync 3.2.4以降はリモートパスを自身でエスケープするため、パスはそのまま渡します。
+///
+/// # 引数
+/// * `sources` - コピー元（1つ以上）
+/// * `dst` - コピー先
+///
+/// # 戻り値
+/// `rsync -a [--bwlimit=<limit>] [-e "ssh ..."] <src>... <dst>` 形式のargv、またはエラー
+pub fn rsync_argv(sources: &[Location], dst: &Location) -> Result<Vec<String>> {
+    if !dst.is_local() && sources.iter().any(|s| !s.is_local()) {
+        return Err(Error::Unsupported(
+            "rsyncはリモート間のコピーに対応していません".to_string(),
+        ));
+    }
+
+    let mut argv = vec!["rsync".to_string(), "-a".to_string()];
+    if let Some(endpoint) = transfer_endpoint(sources, dst) {
+        if let Some(limit) = endpoint.bwlimit {
+            argv.push(format!("--bwlimit={}", limit));
+        }
+        argv.push("-e".to_string());
+        argv.push(rsync_shell(endpoint));
+    }
+    argv.extend(sources.iter().map(Location::to_arg));
+    argv.push(dst.to_arg());
+    Ok(argv)
+}
+
+/// プログラムがPATH上に存在するかどうかを返します
+///
+/// # 引数
+/// * `program` - プログラム名
+pub fn is_available(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// argvから実行可能なCommandを作成します
+///
+/// # 引数
+/// * `argv` - プログラム名を先頭に含む引数列
+pub fn command(argv: &[String]) -> Command {
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..]);
+    cmd
+}
+
+/// 転送で使用する接続先を返します
+///
+/// 最初のリモートのコピー元、なければコピー先の接続先を返します。
+fn transfer_endpoint<'a>(sources: &'a [Location], dst: &'a Location) -> Option<&'a Endpoint> {
+    sources.iter().find_map(Location::endpoint).or_else(|| dst.endpoint())
+}
+
+/// 接続先に応じたポート・秘密鍵・圧縮・暗号方式・known_hostsのオプションを返します
+///
+/// sshは `-p`、scpは `-P` でポートを指定するため、フラグを引数で受け取ります。
+fn ssh_options(endpoint: &Endpoint, port_flag: &str) -> Vec<String> {
+    let mut options = vec![port_flag.to_string(), endpoint.port.to_string()];
+    // 秘密鍵が指定されている場合は追加
+    if let Some(ref key_path) = endpoint.key_path {
+        options.push("-i".to_string());
+        options.push(key_path.clone());
+    }
+    if endpoint.compression {
+        options.push("-C".to_string());
+    }
+    if let Some(ref cipher) = endpoint.cipher {
+        options.push("-c".to_string());
+        options.push(cipher.clone());
+    }
+    if let Some(ref known_hosts) = endpoint.known_hosts {
+        options.push("-o".to_string());
+        options.push(format!("UserKnownHostsFile={}", known_hosts));
+    }
+    options
+}
+
+/// rsyncの `-e` オプションに渡すリモートシェル文字列を構築します
+fn rsync_shell(endpoint: &Endpoint) -> String {
+    let mut shell = vec!["ssh".to_string()];
+    shell.extend(ssh_options(endpoint, "-p").iter().map(|o| shell_quote(o)));
+    shell.join(" ")
+}
+
+/// 文字列をシェル向けにクォートします
+///
+/// 安全な文字のみで構成される場合はそのまま返し、
+/// それ以外はシングルクォートで囲みます。
+pub fn shell_quote(s: &str) -> String {
+    let safe = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@,+%".contains(c));
+    if safe {
+        s.to_string()
+    } else {
+        single_quote(s)
+    }
+}
+
+/// リモートパスをリモートシェル向けにエスケープします
+///
+/// 空白やシェルの特殊文字をバックスラッシュでエスケープします。
+/// globの特殊文字（`*?[]`）と先頭の`~`はリモート側で展開されるよう残します。
+/// 改行はバックスラッシュでは行の継続になるため、シングルクォートで囲みます。
+///
+/// globを展開してはならないパス（削除や書き込みの対象など）には [`quote_literal`] を使用してください。
+pub fn remote_escape(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for (i, c) in path.chars().enumerate() {
+        let keep = c.is_ascii_alphanumeric()
+            || "-_./=:@,+%*?[]".contains(c)
+            || (c == '~' && i == 0)
+            || !c.is_ascii();
+        if c == '\n' {
+            escaped.push_str("'\n'");
+            continue;
+        }
+        if !keep {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// リモートパスをglobを展開しないリテラルとしてリモートシェル向けにクォートします
+///
+/// 先頭の`~`または`~user`はホームディレクトリに展開されるようクォートの外に残し、
+/// 残りはシングルクォートで囲みます（例: `~/a b*` → `~/'a b*'`）。
+pub fn quote_literal(path: &str) -> String {
+    let (tilde, rest) = match path.find('/') {
+        Some(i) => (&path[..i], &path[i..]),
+        None => (path, ""),
+    };
+    let is_tilde = tilde.starts_with('~')
+        && tilde[1..].chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    match (is_tilde, rest) {
+        (true, "") => tilde.to_string(),
+        (true, rest) => format!("{}/{}", tilde, single_quote(&rest[1..])),
+        (false, _) => single_quote(path),
+    }
+}
+
+/// 文字列を常にシングルクォートで囲みます
+fn single_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(path: &str) -> Location {
+        Location::Remote {
+            endpoint: Endpoint::new("u@prod", 22),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn quote_literal_keeps_leading_tilde() {
+        assert_eq!(quote_literal("~/a b*"), "~/'a b*'");
+        assert_eq!(quote_literal("~deploy/x"), "~deploy/'x'");
+        assert_eq!(quote_literal("~"), "~");
+        assert_eq!(quote_literal("/srv/[ab]"), "'/srv/[ab]'");
+        assert_eq!(quote_literal("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn remote_escape_quotes_newlines() {
+        assert_eq!(remote_escape("a\nb"), "a'\n'b");
+        assert_eq!(remote_escape("~/logs/*.log"), "~/logs/*.log");
+    }
+
+    #[test]
+    fn scp_destination_is_literal() {
+        let argv = scp_argv(&[remote("logs/*.log")], &remote("backup[1]"));
+        assert_eq!(argv[argv.len() - 2], "u@prod:logs/*.log");
+        assert_eq!(argv[argv.len() - 1], "u@prod:'backup[1]'");
+
+        let argv = scp_argv(&[Location::Local("a.txt".to_string())], &remote(""));
+        assert_eq!(argv.last().unwrap(), "u@prod:");
+    }
+}
@@ -0,0 +1,313 @@
+// 監査ログ機能
+//
+// このモジュールは、copy・sync（watch）・editで転送したファイルの記録を
+// JSON Lines形式の監査ログに追記し、ホストや日付で検索して表示します。
+
+use crate::checksum;
+use crate::config::Config;
+use crate::progress;
+use crate::resolve::Location;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// 監査ログの1件の記録
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// 記録日時（RFC 3339形式）
+    pub timestamp: String,
+    /// 操作したローカルユーザー
+    pub user: String,
+    /// 操作の種類（copy、sync、sync-delete、edit）
+    pub action: String,
+    /// 入力されたままのコピー元
+    pub sources: Vec<String>,
+    /// 入力されたままのコピー先
+    pub destination: String,
+    /// 解決後のコピー元（connection:path形式）
+    pub resolved_sources: Vec<String>,
+    /// 解決後のコピー先（connection:path形式）
+    pub resolved_destination: String,
+    /// 関係するホスト（エイリアス、直接指定の場合は接続文字列）
+    pub hosts: Vec<String>,
+    /// 転送したバイト数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// 転送したファイルのSHA-256（複数ファイルの場合は一覧のSHA-256）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// 使用したバックエンド
+    pub backend: String,
+    /// 終了コード（成功時は0）
+    pub exit_code: i32,
+    /// 失敗した場合のエラーメッセージ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// 現在の日時とユーザーで記録を作成します
+    ///
+    /// # 引数
+    /// * `action` - 操作の種類
+    /// * `sources` - 入力されたままのコピー元
+    /// * `destination` - 入力されたままのコピー先
+    /// * `backend` - 使用したバックエンド
+    pub fn new(action: &str, sources: &[String], destination: &str, backend: &str) -> Self {
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("LOGNAME"))
+            .ok()
+            .or_else(|| {
+                let output = std::process::Command::new("whoami").output().ok()?;
+                Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|u| !u.is_empty())
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+        AuditRecord {
+            timestamp: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            user,
+            action: action.to_string(),
+            sources: sources.to_vec(),
+            destination: destination.to_string(),
+            resolved_sources: Vec::new(),
+            resolved_destination: String::new(),
+            hosts: Vec::new(),
+            bytes: None,
+            checksum: None,
+            backend: backend.to_string(),
+            exit_code: 0,
+            error: None,
+        }
+    }
+
+    /// 解決後のコピー元とコピー先、関係するホストを設定します
+    pub fn locations(mut self, sources: &[Location], destination: &Location) -> Self {
+        self.set_locations(sources, destination);
+        self
+    }
+
+    /// 解決後のコピー元とコピー先、関係するホストを設定します（設定済みの記録に対して使用）
+    pub fn set_locations(&mut self, sources: &[Location], destination: &Location) {
+        self.resolved_sources = sources.iter().map(Location::to_arg).collect();
+        self.resolved_destination = destination.to_arg();
+        for endpoint in sources.iter().chain(std::iter::once(destination)).filter_map(Location::endpoint) {
+            let host = endpoint.alias.clone().unwrap_or_else(|| endpoint.connection.clone());
+            if !self.hosts.contains(&host) {
+                self.hosts.push(host);
+            }
+        }
+    }
+
+    /// 操作の結果を設定します
+    pub fn result<T>(mut self, result: &Result<T, Box<dyn std::error::Error>>) -> Self {
+        if let Err(e) = result {
+            self.exit_code = 1;
+            self.error = Some(e.to_string());
+        }
+        self
+    }
+}
+
+/// 監査ログに記録を追記します
+///
+/// 書き込みに失敗しても操作自体は完了しているため、警告を表示するだけでエラーにはしません。
+///
+/// # 引数
+/// * `config` - 現在の設定（監査ログの場所）
+/// * `record` - 追記する記録
+pub fn record(config: &Config, record: &AuditRecord) {
+    match log_file(config) {
+        Ok(path) => append(&path, record),
+        Err(e) => println!("{}: 監査ログに記録できませんでした: {}", "WARN".yellow(), e),
+    }
+}
+
+/// 事前の確認で拒否された操作を監査ログに記録し、そのエラーを返します
+///
+/// 名前解決の失敗や、書き込み先の安全確認で拒否された操作の記録に使用します。
+///
+/// # 引数
+/// * `config` - 現在の設定（監査ログの場所）
+/// * `record` - 記録（終了コードとエラーメッセージはこの関数で設定）
+/// * `error` - 拒否した理由
+pub fn refused<T>(
+    config: &Config,
+    record: AuditRecord,
+    error: Box<dyn std::error::Error>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let result = Err(error);
+    self::record(config, &record.result(&result));
+    result
+}
+
+/// 指定した監査ログに記録を追記します
+///
+/// ログファイルは所有者のみ読み書きできる権限（0600）で作成します。
+pub fn append(path: &Path, record: &AuditRecord) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    })();
+    if let Err(e) = result {
+        println!("{}: 監査ログ '{}' に記録できませんでした: {}", "WARN".yellow(), path.display(), e);
+    }
+}
+
+/// 監査ログのパスを返します
+///
+/// 設定の `audit_log` を使用し、未設定の場合は `~/.config/sshportal/audit.jsonl` を返します。
+pub fn log_file(config: &Config) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match config.audit_log {
+        Some(ref path) => Ok(PathBuf::from(Config::expand_path(path)?)),
+        None => Ok(Config::config_dir()?.join("audit.jsonl")),
+    }
+}
+
+/// 転送したファイルのチェックサムを求めます
+///
+/// 単一のファイルの場合はそのファイルのSHA-256、それ以外の場合は
+/// `sha256sum` 形式の一覧（ハッシュ、2つの空白、パス）のSHA-256を返します。
+///
+/// # 引数
+/// * `files` - 転送後の検証で求めたファイルのパスとSHA-256
+///
+/// # 戻り値
+/// SHA-256。ファイルがない（検証していない）場合はNone
+pub fn manifest_checksum(files: &[(String, String)]) -> Option<String> {
+    match files {
+        [] => None,
+        [(_, sha256)] => Some(sha256.clone()),
+        files => {
+            let manifest: String = files.iter().map(|(path, sha256)| format!("{}  {}\n", sha256, path)).collect();
+            Some(checksum::sha256_hex(manifest.as_bytes()))
+        }
+    }
+}
+
+/// 監査ログを検索して表示します
+///
+/// # 引数
+/// * `host` - このホスト（エイリアスまたは接続文字列）に関係する記録のみ表示
+/// * `since` - この日付（YYYY-MM-DD）以降の記録のみ表示
+/// * `until` - この日付（YYYY-MM-DD）以前の記録のみ表示
+/// * `json` - JSON Lines形式のまま表示するかどうか
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn show_log(
+    host: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+    for date in since.iter().chain(until.iter()) {
+        if chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+            println!("{}: 日付は YYYY-MM-DD の形式で指定してください: {}", "ERROR".red(), date);
+            return Ok(());
+        }
+    }
+
+    let path = log_file(&config)?;
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("監査ログはまだありません（{}）", path.display());
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // エイリアスと接続文字列のどちらで検索しても、同じホストの記録に一致させる
+    let names: Vec<&str> = match host {
+        Some(host) => {
+            let connection = config.hosts.get(host).map(|h| h.connection.as_str()).unwrap_or(host);
+            let mut names = vec![host, connection];
+            names.extend(
+                config
+                    .hosts
+                    .iter()
+                    .filter(|(_, h)| h.connection == connection)
+                    .map(|(alias, _)| alias.as_str()),
+            );
+            names
+        }
+        None => Vec::new(),
+    };
+    let mut shown = 0;
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: AuditRecord = match serde_json::from_str(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                println!("{}: {}行目を解析できません: {}", "WARN".yellow(), number + 1, e);
+                continue;
+            }
+        };
+
+        let date = record.timestamp.get(..10).unwrap_or("");
+        if since.is_some_and(|since| date < since) || until.is_some_and(|until| date > until) {
+            continue;
+        }
+        if host.is_some() && !record.hosts.iter().any(|h| names.contains(&h.as_str())) {
+            continue;
+        }
+
+        if json {
+            println!("{}", line);
+        } else {
+            print_record(&record);
+        }
+        shown += 1;
+    }
+
+    if shown == 0 && !json {
+        println!("該当する記録はありません");
+    }
+    Ok(())
+}
+
+/// 記録を1行で表示します
+fn print_record(record: &AuditRecord) {
+    let status = if record.exit_code == 0 { "✓".green() } else { "✗".red() };
+    let timestamp = record.timestamp.get(..19).unwrap_or(&record.timestamp).replace('T', " ");
+    let mut info = vec![record.backend.clone()];
+    if let Some(bytes) = record.bytes {
+        info.push(progress::format_bytes(bytes));
+    }
+    if let Some(ref checksum) = record.checksum {
+        info.push(format!("sha256:{}", checksum.get(..12).unwrap_or(checksum)));
+    }
+    let target = if record.resolved_sources.is_empty() {
+        record.resolved_destination.clone()
+    } else {
+        format!("{} → {}", record.resolved_sources.join(", "), record.resolved_destination)
+    };
+    println!(
+        "{} {} {} {:<11} {} {}",
+        timestamp.dimmed(),
+        status,
+        record.user,
+        record.action.cyan(),
+        target,
+        format!("({})", info.join(", ")).dimmed()
+    );
+    if let Some(ref error) = record.error {
+        println!("    {}", error.red());
+    }
+}
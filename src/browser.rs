@@ -0,0 +1,98 @@
+// リモートディレクトリブラウザ
+//
+// このモジュールは、ssh経由でリモートのファイルシステムを対話的に辿り、
+// パスエイリアスやコピー元/コピー先として使うエントリを選択する機能を提供します。
+
+use crate::remote;
+use crate::resolve::Endpoint;
+use colored::*;
+use std::io::{self, Write};
+
+/// プレビューで表示する行数
+const PREVIEW_LINES: usize = 20;
+
+/// リモートディレクトリを対話的に閲覧し、エントリを選択します
+/// 
+/// 番号でディレクトリに移動またはファイルを選択し、`..`で親ディレクトリへ、
+/// `p 番号`でファイルをプレビュー、`.`で現在のディレクトリを選択、`q`で中止します。
+/// 
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `start` - 開始ディレクトリ（空の場合はホームディレクトリ）
+/// 
+/// # 戻り値
+/// 選択されたエントリの絶対パス、中止した場合はNone
+pub fn browse(endpoint: &Endpoint, start: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut dir = if start.is_empty() { "~".to_string() } else { start.to_string() };
+    
+    loop {
+        let listing = remote::list_dir(endpoint, &dir)?;
+        dir = listing.path.clone();
+        
+        println!("\n{} {}", format!("{}:", endpoint.connection).cyan(), dir.bold());
+        println!("  {} ..", " 0".dimmed());
+        for (i, entry) in listing.entries.iter().enumerate() {
+            let number = format!("{:>2}", i + 1);
+            if entry.ends_with('/') {
+                println!("  {} {}", number.dimmed(), entry.blue().bold());
+            } else {
+                println!("  {} {}", number.dimmed(), entry);
+            }
+        }
+        
+        print!("番号で移動/選択, p 番号:プレビュー, .:このディレクトリを選択, q:中止 > ");
+        io::stdout().flush()?;
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(None);
+        }
+        let input = input.trim();
+        
+        match input {
+            "q" => return Ok(None),
+            "." => return Ok(Some(dir)),
+            ".." | "0" => dir = parent(&dir),
+            _ => {
+                // プレビュー
+                if let Some(number) = input.strip_prefix("p ") {
+                    match entry_at(&listing.entries, number.trim()) {
+                        Some(entry) if !entry.ends_with('/') => {
+                            let content = remote::preview(endpoint, &join(&dir, entry), PREVIEW_LINES)?;
+                            println!("{}", format!("--- {} ---", entry).dimmed());
+                            print!("{}", content);
+                            println!("{}", "---".dimmed());
+                        }
+                        Some(_) => println!("{}: ディレクトリはプレビューできません", "WARN".yellow()),
+                        None => println!("{}: 無効な番号です", "ERROR".red()),
+                    }
+                    continue;
+                }
+                
+                match entry_at(&listing.entries, input) {
+                    Some(entry) if entry.ends_with('/') => dir = join(&dir, entry.trim_end_matches('/')),
+                    Some(entry) => return Ok(Some(join(&dir, entry))),
+                    None => println!("{}: 無効な入力です", "ERROR".red()),
+                }
+            }
+        }
+    }
+}
+
+/// 番号（1始まり）に対応するエントリを返します
+fn entry_at<'a>(entries: &'a [String], number: &str) -> Option<&'a str> {
+    let index = number.parse::<usize>().ok()?.checked_sub(1)?;
+    entries.get(index).map(String::as_str)
+}
+
+/// ディレクトリとエントリ名を連結します
+fn join(dir: &str, entry: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), entry)
+}
+
+/// 親ディレクトリのパスを返します
+fn parent(dir: &str) -> String {
+    match dir.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/".to_string(),
+        Some((parent, _)) => parent.to_string(),
+    }
+}
@@ -0,0 +1,23 @@
+// チェックサム計算機能
+//
+// このモジュールは、転送内容の検証に使用するSHA-256の計算を提供します。
+
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+/// バイト列のSHA-256を16進文字列で返します
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// 読み込み元の内容のSHA-256を16進文字列で返します
+pub fn sha256_reader<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// バイト列を小文字の16進文字列に変換します
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,212 @@
+// 上書きの検出機能
+//
+// このモジュールは、転送前にコピー元とコピー先のファイル一覧を比較して
+// 上書きされるファイルを求め、確認のプロンプトや既存ファイルの退避を行います。
+
+use crate::filter::TransferFilter;
+use crate::progress;
+use crate::remote;
+use crate::resolve::Location;
+use crate::verify::VerifyPair;
+use colored::*;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// プロンプトに一覧表示するファイル数の上限
+const MAX_LISTED: usize = 20;
+
+/// 既存のファイルの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// そのまま上書きする
+    Overwrite,
+    /// タイムスタンプ付きの名前に退避してから転送する
+    Backup,
+    /// 既存のファイルは転送しない
+    Skip,
+    /// 転送を中止する
+    Abort,
+}
+
+/// 転送によって上書きされるファイルを求めます
+///
+/// ローカルはファイルシステムを、リモートは接続先ごとに1回のssh接続で一覧を取得し、
+/// コピー元のファイルとコピー先の既存のファイルを比較します。
+/// 除外パターンに一致するファイルは転送されないため含めません。
+///
+/// # 引数
+/// * `pairs` - コピー元とコピー先の組（`verify::plan` で求めたもの）
+/// * `filter` - 転送時に適用する除外・包含パターン
+///
+/// # 戻り値
+/// 組ごとの、上書きされるファイル（コピー元からの相対パス、単一ファイルの場合は空文字列）
+pub fn find(pairs: &[VerifyPair], filter: &TransferFilter) -> Result<Vec<BTreeSet<String>>, Box<dyn std::error::Error>> {
+    let matcher = filter.matcher()?;
+    let sources = list_files(&pairs.iter().map(|pair| &pair.src).collect::<Vec<_>>())?;
+    let existing = list_files(&pairs.iter().map(|pair| &pair.dst).collect::<Vec<_>>())?;
+
+    Ok(sources
+        .iter()
+        .zip(&existing)
+        .map(|(sources, existing)| {
+            sources
+                .intersection(existing)
+                .filter(|relative| relative.is_empty() || !matcher.is_ignored(Path::new(relative), false))
+                .cloned()
+                .collect()
+        })
+        .collect())
+}
+
+/// 上書きされるファイルのコピー先での表示名を返します
+///
+/// # 引数
+/// * `pair` - コピー元とコピー先の組
+/// * `relative` - コピー元からの相対パス
+pub fn display_path(pair: &VerifyPair, relative: &str) -> String {
+    match relative {
+        "" => pair.dst.to_arg(),
+        relative => format!("{}/{}", pair.dst.to_arg().trim_end_matches('/'), relative),
+    }
+}
+
+/// 上書きされるファイルを一覧し、扱いを確認します
+///
+/// # 引数
+/// * `files` - 上書きされるファイルの表示名
+///
+/// # 戻り値
+/// 選択された扱い
+pub fn prompt(files: &[String]) -> Result<Resolution, Box<dyn std::error::Error>> {
+    println!("{}: 次の{}件のファイルが上書きされます", "WARN".yellow(), files.len());
+    for file in files.iter().take(MAX_LISTED) {
+        println!("  {}", file.yellow());
+    }
+    if files.len() > MAX_LISTED {
+        println!("  ほか{}件", files.len() - MAX_LISTED);
+    }
+
+    loop {
+        print!("[o]上書き / [b]退避して上書き / [s]既存のファイルをスキップ / [a]中止: ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        match answer.trim().to_lowercase().as_str() {
+            "o" | "overwrite" => return Ok(Resolution::Overwrite),
+            "b" | "backup" => return Ok(Resolution::Backup),
+            "s" | "skip" => return Ok(Resolution::Skip),
+            "a" | "abort" | "" => return Ok(Resolution::Abort),
+            _ => println!("{}: o、b、s、a のいずれかを入力してください", "WARN".yellow()),
+        }
+    }
+}
+
+/// 上書きされるファイルを `名前.YYYYMMDD-HHMMSS` に退避します
+///
+/// リモートのファイルは1回のssh接続でまとめて名前を変更します。
+///
+/// # 引数
+/// * `pairs` - コピー元とコピー先の組
+/// * `conflicts` - 組ごとの上書きされるファイル（`find` の結果）
+///
+/// # 戻り値
+/// 退避したファイル数
+pub fn backup(pairs: &[VerifyPair], conflicts: &[BTreeSet<String>]) -> Result<usize, Box<dyn std::error::Error>> {
+    let suffix = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut renamed = 0;
+
+    for (pair, files) in pairs.iter().zip(conflicts) {
+        let renames: Vec<(String, String)> = files
+            .iter()
+            .map(|relative| {
+                let path = match relative.as_str() {
+                    "" => pair.dst.path().to_string(),
+                    relative => format!("{}/{}", pair.dst.path().trim_end_matches('/'), relative),
+                };
+                let backup = format!("{}.{}", path, suffix);
+                (path, backup)
+            })
+            .collect();
+        if renames.is_empty() {
+            continue;
+        }
+
+        match &pair.dst {
+            Location::Local(_) => {
+                for (from, to) in &renames {
+                    fs::rename(from, to)?;
+                }
+            }
+            Location::Remote { endpoint, .. } => remote::rename_files(endpoint, &renames)?,
+        }
+        renamed += renames.len();
+    }
+
+    if renamed > 0 {
+        println!(
+            "{}: 既存の{}件のファイルを *.{} に退避しました",
+            "INFO".green(),
+            renamed,
+            suffix
+        );
+    }
+    Ok(renamed)
+}
+
+/// 各パスのファイル一覧を取得します
+///
+/// リモートのパスは接続先ごとにまとめて1回のssh接続で取得します。
+fn list_files(locations: &[&Location]) -> Result<Vec<BTreeSet<String>>, Box<dyn std::error::Error>> {
+    let mut lists = vec![BTreeSet::new(); locations.len()];
+    let mut done = vec![false; locations.len()];
+
+    for (i, location) in locations.iter().enumerate() {
+        if done[i] {
+            continue;
+        }
+        match location {
+            Location::Local(path) => lists[i] = local_files(Path::new(path))?,
+            Location::Remote { endpoint, .. } => {
+                let indices: Vec<usize> = (i..locations.len())
+                    .filter(|&j| !done[j] && locations[j].endpoint() == Some(endpoint))
+                    .collect();
+                let paths: Vec<String> = indices.iter().map(|&j| locations[j].path().to_string()).collect();
+                for (j, list) in indices.iter().zip(remote::list_files(endpoint, &paths)?) {
+                    lists[*j] = list;
+                    done[*j] = true;
+                }
+            }
+        }
+        done[i] = true;
+    }
+    Ok(lists)
+}
+
+/// ローカルのファイル、またはディレクトリ内のファイルを一覧します
+///
+/// ディレクトリ以外の既存のパスは空文字列、存在しないパスは空の集合を返します。
+fn local_files(path: &Path) -> Result<BTreeSet<String>, Box<dyn std::error::Error>> {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => {}
+        Ok(_) => return Ok(BTreeSet::from([String::new()])),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut files = BTreeSet::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let scan = progress::scan_local(&entry.path())?;
+        for (file, _) in scan.files {
+            let Ok(relative) = file.strip_prefix(path) else { continue };
+            let parts: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            files.insert(parts.join("/"));
+        }
+    }
+    Ok(files)
+}
@@ -4,8 +4,19 @@
 // 適切な機能モジュールに処理を委譲します。
 
 use clap::{Parser, Subcommand};
+use crate::audit;
+use crate::compare;
+use crate::doctor;
+use crate::edit;
 use crate::host;
+use crate::hostkey;
+use crate::jobs;
+use crate::key;
 use crate::path;
+use crate::config::{Config, SafetyLevel};
+use crate::safety;
+use crate::transfer::{self, CopyOptions};
+use crate::watch::{self, WatchOptions};
 
 /// sshportalのメインコマンドライン構造体
 /// 
@@ -65,14 +76,267 @@ pub enum Commands {
     /// パスエイリアスを使用してSCPでファイルをコピー
     #[command(about = "パスエイリアスを使用してSCPでファイルをコピー")]
     Copy {
-        #[arg(help = "コピー元パス（エイリアスまたは実際のパス）")]
+        #[arg(
+            required = true,
+            num_args = 2..,
+            value_name = "SRC... DST",
+            help = "コピー元パス（複数可、globも可）とコピー先パス（最後の引数）"
+        )]
+        paths: Vec<String>,
+        #[command(flatten)]
+        options: CopyOptions,
+    },
+    /// リモートのファイルをローカルに取得
+    #[command(about = "リモートのファイルをローカルに取得")]
+    Pull {
+        #[arg(help = "コピー元（host:エイリアスまたはhost:パス）")]
         src: String,
-        #[arg(help = "コピー先パス（エイリアスまたはhost:path）")]
+        #[arg(help = "コピー先のローカルパス（省略時はカレントディレクトリ）")]
+        local: Option<String>,
+        #[command(flatten)]
+        options: CopyOptions,
+    },
+    /// ローカルのファイルをホストに送信
+    #[command(about = "ローカルのファイルをホストに送信")]
+    Push {
+        #[arg(
+            required = true,
+            num_args = 2..,
+            value_name = "LOCAL... HOST[:ALIAS]",
+            help = "コピー元のローカルパス（複数可）と送信先（host のみの場合は default_upload）"
+        )]
+        paths: Vec<String>,
+        #[command(flatten)]
+        options: CopyOptions,
+    },
+    /// リモートファイルをローカルのエディタで編集
+    #[command(about = "リモートファイルをローカルのエディタで編集")]
+    Edit {
+        #[arg(help = "編集するファイル（host:エイリアスまたはhost:パス）")]
+        spec: String,
+        #[arg(long, help = "sudoで読み込み、sudo teeで書き込む")]
+        sudo: bool,
+        #[arg(short = 'y', long, help = "保護されたホストへの書き込みの確認を省略（SSHPORTAL_ALLOW_YES=1が必要）")]
+        yes: bool,
+    },
+    /// ローカルまたはリモートのファイル・ディレクトリの差分を表示
+    #[command(about = "ローカルまたはリモートのファイル・ディレクトリの差分を表示")]
+    Diff {
+        #[arg(help = "比較元（ローカルパス、エイリアスまたはhost:パス）")]
+        old: String,
+        #[arg(help = "比較先（ローカルパス、エイリアスまたはhost:パス）")]
+        new: String,
+    },
+    /// ローカルディレクトリを監視してリモートに同期
+    #[command(about = "ローカルディレクトリを監視してリモートに同期")]
+    Watch {
+        #[arg(help = "監視するローカルディレクトリ（エイリアスまたはパス）")]
+        local: String,
+        #[arg(help = "同期先（host:エイリアスまたはhost:パス）")]
         dst: String,
+        #[command(flatten)]
+        options: WatchOptions,
+    },
+    /// 監査ログを表示
+    #[command(about = "転送の監査ログを表示")]
+    Log {
+        #[arg(long, help = "このホストに関係する記録のみ表示")]
+        host: Option<String>,
+        #[arg(long, value_name = "YYYY-MM-DD", help = "この日付以降の記録のみ表示")]
+        since: Option<String>,
+        #[arg(long, value_name = "YYYY-MM-DD", help = "この日付以前の記録のみ表示")]
+        until: Option<String>,
+        #[arg(long, help = "JSON Lines形式で出力")]
+        json: bool,
+    },
+    /// バックグラウンドジョブの一覧表示と管理
+    #[command(about = "バックグラウンドジョブの一覧表示と管理")]
+    Jobs {
+        #[command(subcommand)]
+        action: Option<JobsAction>,
+    },
+    /// ホストのデフォルトのアップロード先を設定
+    #[command(about = "ホストのデフォルトのアップロード先を設定")]
+    SetUpload {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+        #[arg(help = "パスエイリアスまたはパス（省略時は設定を解除）")]
+        path: Option<String>,
+    },
+    /// ホストの安全レベルを設定
+    #[command(about = "ホストの安全レベルを設定（protected: 書き込み時に確認、read-only: 書き込みを拒否）")]
+    SetSafety {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+        #[arg(value_enum, help = "安全レベル")]
+        level: SafetyLevel,
+    },
+    /// ホストの転送設定（帯域制限・圧縮・暗号方式）を変更
+    #[command(about = "ホストの転送設定（帯域制限・圧縮・暗号方式）を変更")]
+    SetTransfer {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+        #[arg(long, value_name = "KIB", help = "帯域制限（KiB/s、0で解除）")]
+        bwlimit: Option<u32>,
+        #[arg(long, value_name = "BOOL", help = "SSHの圧縮（true/false）")]
+        compression: Option<bool>,
+        #[arg(long, value_name = "CIPHER", help = "SSHの暗号方式（空文字で解除）")]
+        cipher: Option<String>,
+        #[arg(long, conflicts_with_all = ["bwlimit", "compression", "cipher"], help = "全ての転送設定を解除")]
+        clear: bool,
     },
     /// パスエイリアスを追加（インタラクティブ）
     #[command(about = "パスエイリアスを追加（インタラクティブ）")]
-    AddPaths,
+    AddPaths {
+        #[arg(long, help = "リモートパスの存在をssh経由で確認")]
+        verify: bool,
+    },
+    /// リモートパスエイリアスの存在を確認
+    #[command(about = "リモートパスエイリアスの存在を確認")]
+    CheckPaths {
+        #[arg(help = "確認するホスト（省略時は全ホスト）")]
+        host: Option<String>,
+    },
+    /// パスエイリアスのデフォルトの除外パターンを追加
+    #[command(about = "パスエイリアスのデフォルトの除外パターンを追加")]
+    AddExclude {
+        #[arg(help = "パスのエイリアス名")]
+        alias: String,
+        #[arg(required = true, help = "除外パターン（複数可）")]
+        patterns: Vec<String>,
+    },
+    /// パスエイリアスのデフォルトの除外パターンを削除
+    #[command(about = "パスエイリアスのデフォルトの除外パターンを削除")]
+    RemoveExclude {
+        #[arg(help = "パスのエイリアス名")]
+        alias: String,
+        #[arg(help = "削除するパターン（省略時は全て削除）")]
+        patterns: Vec<String>,
+    },
+    /// パステンプレートの変数を追加
+    #[command(about = "パステンプレートの変数を追加")]
+    AddVar {
+        #[arg(help = "変数名")]
+        name: String,
+        #[arg(help = "変数の値")]
+        value: String,
+    },
+    /// パステンプレートの変数を削除
+    #[command(about = "パステンプレートの変数を削除")]
+    RemoveVar {
+        #[arg(help = "変数名")]
+        name: String,
+    },
+    /// パステンプレートの変数一覧を表示
+    #[command(about = "パステンプレートの変数一覧を表示")]
+    ListVars,
+    /// パス指定を解決して実際のパスを表示
+    #[command(about = "パス指定を解決して実際のパスを表示")]
+    Resolve {
+        #[arg(help = "パス指定（エイリアスまたはhost:path）")]
+        spec: String,
+    },
+    /// ホスト専用のSSH鍵の生成と更新
+    #[command(about = "ホスト専用のSSH鍵を生成・更新して登録")]
+    Key {
+        #[command(subcommand)]
+        action: KeyAction,
+    },
+    /// ホスト鍵の指紋の表示・更新・検証
+    #[command(about = "ホスト鍵の指紋を表示・更新・検証")]
+    Hostkey {
+        #[command(subcommand)]
+        action: HostkeyAction,
+    },
+    /// 設定と環境を診断
+    #[command(about = "設定ファイルと秘密鍵のパーミッション、設定の不整合、必要なコマンドを診断")]
+    Doctor {
+        #[arg(long, help = "パーミッションの修正と旧形式のpathsの移行を行う")]
+        fix: bool,
+    },
+}
+
+/// keyコマンドのサブコマンドの定義
+#[derive(Subcommand)]
+pub enum KeyAction {
+    /// ed25519鍵を生成してホストに登録し、key_pathに設定
+    #[command(about = "ed25519鍵を生成してホストに登録し、key_pathに設定")]
+    New {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+        #[arg(short = 'y', long, help = "保護されたホストの確認を省略（SSHPORTAL_ALLOW_YES=1が必要）")]
+        yes: bool,
+    },
+    /// 新しい鍵に更新し、古い鍵をauthorized_keysから削除
+    #[command(about = "新しい鍵に更新し、古い鍵をauthorized_keysから削除")]
+    Rotate {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+        #[arg(short = 'y', long, help = "保護されたホストの確認を省略（SSHPORTAL_ALLOW_YES=1が必要）")]
+        yes: bool,
+    },
+}
+
+/// hostkeyコマンドのサブコマンドの定義
+#[derive(Subcommand)]
+pub enum HostkeyAction {
+    /// 記録済みのホスト鍵の指紋を表示
+    #[command(about = "記録済みのホスト鍵の指紋を表示")]
+    Show {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+    },
+    /// ホスト鍵を取得し直して記録を更新
+    #[command(about = "ssh-keyscanでホスト鍵を取得し直し、新旧の指紋を確認して記録を更新")]
+    Refresh {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+        #[arg(short = 'y', long, help = "指紋の確認を省略して記録を置き換える")]
+        yes: bool,
+    },
+    /// 現在のホスト鍵が記録と一致するかを確認
+    #[command(about = "現在のホスト鍵が記録と一致するかを確認")]
+    Verify {
+        #[arg(help = "ホストのエイリアス名")]
+        host: String,
+    },
+    /// sshportal専用のknown_hostsファイルを表示または設定
+    #[command(about = "sshportal専用のknown_hostsファイルを表示または設定")]
+    KnownHosts {
+        #[arg(help = "known_hostsファイルのパス（省略時は現在の設定を表示）")]
+        path: Option<String>,
+        #[arg(long, conflicts_with = "path", help = "設定を解除し、sshの既定のファイルを使用")]
+        clear: bool,
+    },
+}
+
+/// jobsコマンドのサブコマンドの定義
+#[derive(Subcommand)]
+pub enum JobsAction {
+    /// ジョブの出力を表示
+    #[command(about = "ジョブの出力を表示")]
+    Log {
+        #[arg(help = "ジョブ番号")]
+        id: u64,
+    },
+    /// ジョブをキャンセル
+    #[command(about = "ジョブをキャンセル（実行中の場合は停止）")]
+    Cancel {
+        #[arg(help = "ジョブ番号")]
+        id: u64,
+    },
+    /// 終了したジョブの記録を削除
+    #[command(about = "終了したジョブの記録と出力を削除")]
+    Clean,
+    /// 同時実行数を表示または設定
+    #[command(about = "同時実行数を表示または設定")]
+    Limit {
+        #[arg(help = "同時実行数（省略時は現在の値を表示）")]
+        limit: Option<usize>,
+    },
+    /// キューを処理するワーカー（内部用）
+    #[command(hide = true)]
+    Worker,
 }
 
 /// コマンドを処理します
@@ -111,12 +375,125 @@ pub fn handle_command(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             path::list_paths_new()
         }
         // ファイル転送コマンド
-        Commands::Copy { src, dst } => {
-            path::copy_files(&src, &dst)
+        Commands::Copy { .. } | Commands::Pull { .. } | Commands::Push { .. } if background(&cli.command) => {
+            // ジョブは端末なしで実行されるため、書き込み先の確認は登録時に行う
+            let confirmed = match background_target(&cli.command) {
+                Some((host, yes)) => safety::check_host(&Config::load()?, host, yes)?,
+                None => false,
+            };
+            jobs::enqueue_current(confirmed)
+        }
+        Commands::Copy { mut paths, options } => {
+            // 最後の引数がコピー先（clapで2つ以上を保証済み）
+            let dst = paths.pop().unwrap_or_default();
+            transfer::copy_files(&paths, &dst, &options)
+        }
+        Commands::Pull { src, local, options } => {
+            transfer::pull(&src, local.as_deref(), &options)
+        }
+        Commands::Push { mut paths, options } => {
+            // 最後の引数が送信先（clapで2つ以上を保証済み）
+            let dst = paths.pop().unwrap_or_default();
+            transfer::push(&paths, &dst, &options)
+        }
+        Commands::Edit { spec, sudo, yes } => {
+            edit::edit_file(&spec, sudo, yes)
+        }
+        Commands::Diff { old, new } => {
+            compare::diff_paths(&old, &new)
+        }
+        Commands::Watch { local, dst, options } => {
+            watch::watch(&local, &dst, &options)
+        }
+        Commands::Log { host, since, until, json } => {
+            audit::show_log(host.as_deref(), since.as_deref(), until.as_deref(), json)
+        }
+        // バックグラウンドジョブコマンド
+        Commands::Jobs { action } => match action {
+            None => jobs::list_jobs(),
+            Some(JobsAction::Log { id }) => jobs::show_log(id),
+            Some(JobsAction::Cancel { id }) => jobs::cancel_job(id),
+            Some(JobsAction::Clean) => jobs::clean_jobs(),
+            Some(JobsAction::Limit { limit }) => jobs::set_limit(limit),
+            Some(JobsAction::Worker) => jobs::run_worker(),
+        },
+        Commands::SetUpload { host, path } => {
+            host::set_default_upload(&host, path.as_deref())
+        }
+        Commands::SetSafety { host, level } => {
+            host::set_safety(&host, level)
+        }
+        Commands::SetTransfer { host, bwlimit, compression, cipher, clear } => {
+            host::set_transfer(&host, bwlimit, compression, cipher.as_deref(), clear)
         }
         // インタラクティブパス管理コマンド
-        Commands::AddPaths => {
-            path::add_path_interactive()
+        Commands::AddPaths { verify } => {
+            path::add_path_interactive(verify)
+        }
+        Commands::CheckPaths { host } => {
+            path::check_paths(host.as_deref())
+        }
+        // 除外パターンコマンド
+        Commands::AddExclude { alias, patterns } => {
+            path::add_excludes(&alias, &patterns)
+        }
+        Commands::RemoveExclude { alias, patterns } => {
+            path::remove_excludes(&alias, &patterns)
+        }
+        // パステンプレート変数コマンド
+        Commands::AddVar { name, value } => {
+            path::add_variable(&name, &value)
+        }
+        Commands::RemoveVar { name } => {
+            path::remove_variable(&name)
+        }
+        Commands::ListVars => {
+            path::list_variables()
+        }
+        Commands::Resolve { spec } => {
+            path::show_resolved(&spec)
+        }
+        // SSH鍵コマンド
+        Commands::Key { action } => match action {
+            KeyAction::New { host, yes } => key::new_key(&host, yes),
+            KeyAction::Rotate { host, yes } => key::rotate_key(&host, yes),
+        },
+        // ホスト鍵コマンド
+        Commands::Hostkey { action } => match action {
+            HostkeyAction::Show { host } => hostkey::show(&host),
+            HostkeyAction::Refresh { host, yes } => hostkey::refresh(&host, yes),
+            HostkeyAction::Verify { host } => hostkey::verify(&host),
+            HostkeyAction::KnownHosts { path, clear } => hostkey::set_known_hosts(path.as_deref(), clear),
+        },
+        Commands::Doctor { fix } => {
+            doctor::run(fix)
         }
     }
-}
\ No newline at end of file
+}
+
+/// 転送コマンドに `--background` が指定されているかどうか
+fn background(command: &Commands) -> bool {
+    match command {
+        Commands::Copy { options, .. } | Commands::Pull { options, .. } | Commands::Push { options, .. } => {
+            options.background
+        }
+        _ => false,
+    }
+}
+
+/// バックグラウンドで実行する転送の書き込み先のホストと `--yes` の指定を返します
+///
+/// pullの書き込み先はローカルのためNoneを返します。
+fn background_target(command: &Commands) -> Option<(&str, bool)> {
+    match command {
+        Commands::Copy { paths, options } => {
+            let (host, _) = paths.last()?.split_once(':')?;
+            (!host.is_empty()).then_some((host, options.yes))
+        }
+        Commands::Push { paths, options } => {
+            let dst = paths.last()?;
+            Some((dst.split_once(':').map_or(dst.as_str(), |(host, _)| host), options.yes))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,158 @@
+// 差分表示コマンド
+//
+// このモジュールは、ローカルまたはリモートのファイル・ディレクトリ同士を比較し、
+// ファイルは unified diff、ディレクトリは追加・削除・変更されたファイルの一覧で表示します。
+
+use crate::config::Config;
+use crate::diff::{self, TreeChange};
+use crate::error::Error;
+use crate::progress;
+use crate::remote::{self, PathKind};
+use crate::resolve::{self, Location};
+use colored::*;
+use std::fs;
+
+/// 2つのパスの差分を表示します
+///
+/// パスの指定は `copy` と同じ方法で解決します（ローカルパス、エイリアス、host:パス）。
+///
+/// # 引数
+/// * `old_spec` - 比較元の指定
+/// * `new_spec` - 比較先の指定
+///
+/// # 戻り値
+/// 成功時は()、いずれかのパスが見つからない場合や種類が異なり比較できない場合を含め、
+/// 失敗時はエラーを返します。
+pub fn diff_paths(old_spec: &str, new_spec: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // 現在の設定を読み込み
+    let config = Config::load()?;
+
+    let old = resolve::resolve_spec(&config, old_spec)?;
+    let new = resolve::resolve_spec(&config, new_spec)?;
+
+    let old_kind = path_kind(&old)?;
+    let new_kind = path_kind(&new)?;
+    // 比較できない場合は終了コードで判別できるようエラーを返す
+    let (Some(old_kind), Some(new_kind)) = (old_kind, new_kind) else {
+        let missing: Vec<String> = [(old_spec, old_kind), (new_spec, new_kind)]
+            .iter()
+            .filter(|(_, kind)| kind.is_none())
+            .map(|(spec, _)| format!("'{}'", spec))
+            .collect();
+        return Err(Error::InvalidPathSpec(format!("{} が見つかりません", missing.join(", "))).into());
+    };
+
+    match (old_kind, new_kind) {
+        (PathKind::File, PathKind::File) => diff_files(&old, &new, old_spec, new_spec),
+        (PathKind::Dir, PathKind::Dir) => diff_dirs(&old, &new),
+        _ => Err(Error::Unsupported(format!(
+            "'{}'（{}）と '{}'（{}）は比較できません",
+            old_spec,
+            old_kind.label(),
+            new_spec,
+            new_kind.label()
+        ))
+        .into()),
+    }
+}
+
+/// パスの種類を返します（シンボリックリンクは辿ります）
+fn path_kind(location: &Location) -> Result<Option<PathKind>, Box<dyn std::error::Error>> {
+    match location {
+        Location::Local(path) => Ok(fs::metadata(path).ok().map(|meta| {
+            if meta.is_dir() {
+                PathKind::Dir
+            } else if meta.is_file() {
+                PathKind::File
+            } else {
+                PathKind::Other
+            }
+        })),
+        Location::Remote { endpoint, path } => Ok(remote::stat_kind(endpoint, path)?),
+    }
+}
+
+/// ファイルの内容を取得します
+fn read_contents(location: &Location) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match location {
+        Location::Local(path) => Ok(fs::read(path)?),
+        Location::Remote { endpoint, path } => Ok(remote::read_file(endpoint, path, false)?),
+    }
+}
+
+/// 2つのファイルの unified diff を表示します
+fn diff_files(
+    old: &Location,
+    new: &Location,
+    old_label: &str,
+    new_label: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let old_contents = read_contents(old)?;
+    let new_contents = read_contents(new)?;
+
+    if old_contents == new_contents {
+        println!("{}: 差分はありません", "INFO".green());
+        return Ok(());
+    }
+    if old_contents.contains(&0) || new_contents.contains(&0) {
+        println!("バイナリファイル {} と {} は異なります", old_label, new_label);
+        return Ok(());
+    }
+
+    let unified = diff::unified_diff(
+        &String::from_utf8_lossy(&old_contents),
+        &String::from_utf8_lossy(&new_contents),
+        old_label,
+        new_label,
+    );
+    for line in unified.lines() {
+        if line.starts_with("---") || line.starts_with("+++") {
+            println!("{}", line.bold());
+        } else if line.starts_with("@@") {
+            println!("{}", line.cyan());
+        } else if line.starts_with('+') {
+            println!("{}", line.green());
+        } else if line.starts_with('-') {
+            println!("{}", line.red());
+        } else {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// 2つのディレクトリ間で追加・削除・変更されたファイルを表示します
+fn diff_dirs(old: &Location, new: &Location) -> Result<(), Box<dyn std::error::Error>> {
+    let changes = diff::compare_trees(&diff::location_tree(old)?, &diff::location_tree(new)?);
+
+    if changes.is_empty() {
+        println!("{}: 差分はありません", "INFO".green());
+        return Ok(());
+    }
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for change in &changes {
+        match change {
+            TreeChange::Added(path) => {
+                added += 1;
+                println!("{} {}", "+".green(), path.green());
+            }
+            TreeChange::Removed(path) => {
+                removed += 1;
+                println!("{} {}", "-".red(), path.red());
+            }
+            TreeChange::Changed { path, old_size, new_size } => {
+                changed += 1;
+                println!(
+                    "{} {} ({} → {})",
+                    "M".yellow(),
+                    path.yellow(),
+                    progress::format_bytes(*old_size),
+                    progress::format_bytes(*new_size)
+                );
+            }
+        }
+    }
+    println!("\n追加: {}  削除: {}  変更: {}", added, removed, changed);
+    Ok(())
+}
@@ -3,15 +3,19 @@
 // このモジュールは、sshportalの設定ファイル（JSON形式）の
 // 読み込み、保存、および設定データ構造の管理を行います。
 
+use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path as FsPath, PathBuf};
 
 /// SSH接続ホストの情報を保持する構造体
 /// 
 /// ホスト名、ユーザー名、ポート番号、秘密鍵パスを含む接続情報を管理します。
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[non_exhaustive]
 pub struct Host {
     /// SSH接続文字列（例: "user@hostname"）
     pub connection: String,
@@ -20,11 +24,73 @@ pub struct Host {
     /// SSH秘密鍵のパス（オプション）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key_path: Option<String>,
+    /// pushでパスを省略した場合のアップロード先（パスエイリアスまたはパス）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_upload: Option<String>,
+    /// 転送時の帯域制限（KiB/s）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bwlimit: Option<u32>,
+    /// SSHの圧縮（`-C`）を使用するかどうか
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<bool>,
+    /// SSHの暗号方式（`-c`、カンマ区切りで優先順に複数指定可）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cipher: Option<String>,
+    /// 書き込みに対する安全レベル
+    #[serde(default, skip_serializing_if = "SafetyLevel::is_normal")]
+    pub safety: SafetyLevel,
+    /// 記録したホスト鍵の指紋（例: "ED25519 SHA256:..."）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_key: Option<String>,
+}
+
+impl Host {
+    /// 接続文字列とポート番号だけを持つホスト情報を作成します
+    ///
+    /// その他の項目は未設定（安全レベルは制限なし）になります。
+    ///
+    /// # 引数
+    /// * `connection` - SSH接続文字列（例: "user@hostname"）
+    /// * `port` - SSH接続ポート番号
+    pub fn new(connection: impl Into<String>, port: u16) -> Self {
+        Host {
+            connection: connection.into(),
+            port,
+            key_path: None,
+            default_upload: None,
+            bwlimit: None,
+            compression: None,
+            cipher: None,
+            safety: SafetyLevel::Normal,
+            host_key: None,
+        }
+    }
+}
+
+/// ホストへの書き込みに対する安全レベル
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyLevel {
+    /// 制限なし
+    #[default]
+    Normal,
+    /// 書き込みの前にホスト名の入力による確認が必要
+    Protected,
+    /// 書き込みを拒否する
+    ReadOnly,
+}
+
+impl SafetyLevel {
+    /// 制限なしかどうか
+    pub fn is_normal(&self) -> bool {
+        *self == SafetyLevel::Normal
+    }
 }
 
 
 /// 旧バージョンとの互換性のためのPath構造体（廃止予定）
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[non_exhaustive]
 pub struct Path {
     /// パスの文字列表現
     pub path: String,
@@ -37,6 +103,7 @@ pub struct Path {
 /// ホスト情報、ローカルパス、ホスト別リモートパスを含み、
 /// JSON形式でシリアライズ/デシリアライズされます。
 #[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct Config {
     /// ホスト名をキーとするホスト情報のマップ
     pub hosts: HashMap<String, Host>,
@@ -46,6 +113,28 @@ pub struct Config {
     /// ホスト別のリモートパス管理 host_name -> (path_alias -> path)
     #[serde(default)]
     pub host_paths: HashMap<String, HashMap<String, String>>,
+    /// 全ホスト共通のリモートパス管理 (alias -> path)
+    /// 
+    /// host_paths に同名のエイリアスがない場合のフォールバックとして使用されます。
+    #[serde(default)]
+    pub remote_paths: HashMap<String, String>,
+    /// パステンプレートで使用するユーザー変数 (name -> value)
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    /// パスエイリアスごとのデフォルトの除外パターン (alias -> patterns)
+    /// 
+    /// エイリアスをコピー元に使用した場合に、転送から除外するパターンです。
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub excludes: HashMap<String, Vec<String>>,
+    /// バックグラウンドジョブの同時実行数（未設定の場合は2）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_jobs: Option<usize>,
+    /// 監査ログのパス（未設定の場合は ~/.config/sshportal/audit.jsonl）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<String>,
+    /// sshportal専用のknown_hostsファイル（未設定の場合はsshの既定のファイルを使用）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub known_hosts: Option<String>,
     /// 旧バージョンとの互換性のためのパス情報（廃止予定）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub paths: Option<HashMap<String, Path>>,
@@ -61,6 +150,12 @@ impl Default for Config {
             hosts: HashMap::new(),
             local_paths: HashMap::new(),
             host_paths: HashMap::new(),
+            remote_paths: HashMap::new(),
+            variables: HashMap::new(),
+            excludes: HashMap::new(),
+            max_jobs: None,
+            audit_log: None,
+            known_hosts: None,
             paths: None,
         }
     }
@@ -70,19 +165,19 @@ impl Config {
     /// 設定ディレクトリのパスを取得します
     /// 
     /// ~/.config/sshportal/ ディレクトリのPathBufを返します。
-    /// ホームディレクトリが見つからない場合はパニックします。
-    pub fn config_dir() -> PathBuf {
-        dirs::home_dir()
-            .expect("ホームディレクトリが見つかりません")
+    /// ホームディレクトリが見つからない場合はエラーを返します。
+    pub fn config_dir() -> Result<PathBuf> {
+        Ok(dirs::home_dir()
+            .ok_or(Error::HomeDirNotFound)?
             .join(".config")
-            .join("sshportal")
+            .join("sshportal"))
     }
 
     /// 設定ファイルのパスを取得します
     /// 
     /// ~/.config/sshportal/config.json のPathBufを返します。
-    pub fn config_file() -> PathBuf {
-        Self::config_dir().join("config.json")
+    pub fn config_file() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("config.json"))
     }
 
     /// 設定ファイルを読み込みます
@@ -92,22 +187,29 @@ impl Config {
     /// 
     /// # 戻り値
     /// 成功時はConfig構造体、失敗時はエラーを返します。
-    pub fn load() -> Result<Config, Box<dyn std::error::Error>> {
-        let config_file = Self::config_file();
+    pub fn load() -> Result<Config> {
+        let config_file = Self::config_file()?;
         
         // 設定ファイルが存在しない場合の処理
         if !config_file.exists() {
-            let config_dir = Self::config_dir();
-            // 設定ディレクトリを作成
-            fs::create_dir_all(&config_dir)?;
             // デフォルト設定を作成・保存
             let default_config = Config::default();
             default_config.save()?;
             return Ok(default_config);
         }
 
+        Self::load_from(&config_file)
+    }
+
+    /// 指定されたファイルから設定を読み込みます
+    /// 
+    /// `load`と異なり、ファイルが存在しない場合はエラーを返します。
+    /// 
+    /// # 引数
+    /// * `file` - 読み込む設定ファイルのパス
+    pub fn load_from(file: &FsPath) -> Result<Config> {
         // 設定ファイルを読み込み、JSONとして解析
-        let content = fs::read_to_string(config_file)?;
+        let content = fs::read_to_string(file)?;
         let config: Config = serde_json::from_str(&content)?;
         Ok(config)
     }
@@ -119,16 +221,33 @@ impl Config {
     /// 
     /// # 戻り値
     /// 成功時は()、失敗時はエラーを返します。
-    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let config_dir = Self::config_dir();
-        // 設定ディレクトリを作成（既に存在する場合は何もしない）
-        fs::create_dir_all(&config_dir)?;
-        
-        let config_file = Self::config_file();
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::config_file()?)
+    }
+
+    /// 指定されたファイルに設定を保存します
+    /// 
+    /// 親ディレクトリが存在しない場合は作成します。新規に作成するディレクトリは0700、
+    /// ファイルは0600の権限になります（既存のものの権限は変更しません）。
+    /// 
+    /// # 引数
+    /// * `file` - 書き込む設定ファイルのパス
+    pub fn save_to(&self, file: &FsPath) -> Result<()> {
+        // 設定ディレクトリを所有者のみアクセスできる権限で作成（既に存在する場合は何もしない）
+        if let Some(dir) = file.parent() {
+            fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        }
+
         // 設定を整形されたJSON形式でシリアライズ
         let content = serde_json::to_string_pretty(self)?;
-        // ファイルに書き込み
-        fs::write(config_file, content)?;
+        // 新規作成時は所有者のみ読み書きできる権限（0600）でファイルに書き込み
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(file)?
+            .write_all(content.as_bytes())?;
         Ok(())
     }
 
@@ -141,18 +260,18 @@ impl Config {
     /// * `path` - 展開するパス文字列
     /// 
     /// # 戻り値
-    /// 展開されたパス文字列
-    pub fn expand_path(path: &str) -> String {
-        if path.starts_with("~/") {
+    /// 展開されたパス文字列。ホームディレクトリが見つからない場合はエラー
+    pub fn expand_path(path: &str) -> Result<String> {
+        if let Some(rest) = path.strip_prefix("~/") {
             // ~/で始まる場合、ホームディレクトリに展開
-            dirs::home_dir()
-                .expect("ホームディレクトリが見つかりません")
-                .join(&path[2..])
+            Ok(dirs::home_dir()
+                .ok_or(Error::HomeDirNotFound)?
+                .join(rest)
                 .to_string_lossy()
-                .to_string()
+                .to_string())
         } else {
             // その他の場合はそのまま返す
-            path.to_string()
+            Ok(path.to_string())
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,136 @@
+// 差分表示機能
+//
+// このモジュールは、2つのテキストの unified diff の生成と、
+// ディレクトリ間のファイル一覧の比較を行います。
+
+use crate::checksum;
+use crate::error::Result;
+use crate::progress;
+use crate::remote;
+use crate::resolve::Location;
+use similar::TextDiff;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// 2つのテキストの unified diff を返します
+///
+/// # 引数
+/// * `old` - 変更前のテキスト
+/// * `new` - 変更後のテキスト
+/// * `old_label` - 変更前のラベル（`---` 行に表示）
+/// * `new_label` - 変更後のラベル（`+++` 行に表示）
+///
+/// # 戻り値
+/// unified diff 形式の文字列（差分がない場合は空文字列）
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
+
+/// ディレクトリ比較に使用するファイルの要約
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDigest {
+    /// ファイルサイズ（バイト）
+    pub size: u64,
+    /// 内容のSHA-256（16進文字列）
+    pub sha256: String,
+}
+
+/// ディレクトリ間の差分の1項目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeChange {
+    /// 比較先にのみ存在するファイル
+    Added(String),
+    /// 比較元にのみ存在するファイル
+    Removed(String),
+    /// 両方に存在し、内容が異なるファイル
+    Changed {
+        /// ディレクトリからの相対パス
+        path: String,
+        /// 比較元のサイズ
+        old_size: u64,
+        /// 比較先のサイズ
+        new_size: u64,
+    },
+}
+
+/// 2つのディレクトリのファイル一覧を比較します
+///
+/// # 引数
+/// * `old` - 比較元の相対パスごとの要約
+/// * `new` - 比較先の相対パスごとの要約
+///
+/// # 戻り値
+/// パス順に並んだ差分の一覧
+pub fn compare_trees(
+    old: &BTreeMap<String, FileDigest>,
+    new: &BTreeMap<String, FileDigest>,
+) -> Vec<TreeChange> {
+    let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    paths
+        .into_iter()
+        .filter_map(|path| match (old.get(path), new.get(path)) {
+            (Some(_), None) => Some(TreeChange::Removed(path.clone())),
+            (None, Some(_)) => Some(TreeChange::Added(path.clone())),
+            (Some(a), Some(b)) if a != b => Some(TreeChange::Changed {
+                path: path.clone(),
+                old_size: a.size,
+                new_size: b.size,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// ローカルまたはリモートのファイル・ディレクトリを要約します
+///
+/// # 引数
+/// * `location` - 要約するファイルまたはディレクトリ
+///
+/// # 戻り値
+/// ディレクトリからの相対パスごとの要約（ファイルの場合は空文字列がキー）
+pub fn location_tree(location: &Location) -> Result<BTreeMap<String, FileDigest>> {
+    match location {
+        Location::Local(path) => local_tree(Path::new(path)),
+        Location::Remote { endpoint, path } => remote::tree_digest(endpoint, path),
+    }
+}
+
+/// ローカルのファイル、またはディレクトリ内の通常ファイルを要約します
+///
+/// シンボリックリンクは辿らず、比較対象に含めません。
+/// ファイルを指定した場合は空文字列をキーとし、存在しない場合は空の結果を返します。
+///
+/// # 引数
+/// * `dir` - 要約するファイルまたはディレクトリ
+///
+/// # 戻り値
+/// ディレクトリからの相対パス（`/`区切り）ごとの要約
+pub fn local_tree(dir: &Path) -> Result<BTreeMap<String, FileDigest>> {
+    let mut tree = BTreeMap::new();
+    if !dir.exists() {
+        return Ok(tree);
+    }
+    for (path, size) in progress::scan_local(dir)?.files {
+        if !fs::symlink_metadata(&path)?.is_file() {
+            continue;
+        }
+        let relative = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let sha256 = checksum::sha256_reader(fs::File::open(&path)?)?;
+        tree.insert(relative, FileDigest { size, sha256 });
+    }
+    Ok(tree)
+}
@@ -0,0 +1,225 @@
+// 設定の診断機能
+//
+// このモジュールは、設定ファイルと秘密鍵のパーミッション、設定内容の不整合、
+// 必要な外部コマンドの有無を確認し、安全に修正できる問題は `--fix` で修正します。
+
+use crate::argv;
+use crate::audit;
+use crate::config::Config;
+use colored::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// 診断結果の集計
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+    fixed: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: &str) {
+        println!("  {} {}", "OK".green(), message);
+    }
+
+    fn warn(&mut self, message: &str) {
+        self.warnings += 1;
+        println!("  {} {}", "WARN".yellow(), message);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.errors += 1;
+        println!("  {} {}", "ERROR".red(), message);
+    }
+
+    fn fixed(&mut self, message: &str) {
+        self.fixed += 1;
+        println!("  {} {}", "FIXED".cyan(), message);
+    }
+}
+
+/// 設定と環境を診断します
+///
+/// 次の項目を確認します:
+/// * 設定ディレクトリ（0700）、設定ファイルと監査ログ（0600）のパーミッション
+/// * 各ホストの秘密鍵の存在とパーミッション（グループ・その他から読めないこと）
+/// * 旧形式の `paths` エントリ、同じ接続先を持つホスト、未登録のホストの `host_paths`
+/// * `ssh`・`scp`・`rsync`・`ssh-keygen` がPATHにあること
+///
+/// `fix` が指定された場合、パーミッションの修正と旧形式の `paths` の移行を行います。
+///
+/// # 引数
+/// * `fix` - 安全に修正できる問題を修正するかどうか
+///
+/// # 戻り値
+/// エラーがない場合は()、エラーが残っている場合はその件数を含むエラー
+pub fn run(fix: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+    let mut report = Report::default();
+
+    println!("{}", "設定ファイル:".bold());
+    check_mode(&mut report, &Config::config_dir()?, 0o700, "設定ディレクトリ", fix)?;
+    check_mode(&mut report, &Config::config_file()?, 0o600, "設定ファイル", fix)?;
+    let audit_log = audit::log_file(&config)?;
+    if audit_log.exists() {
+        check_mode(&mut report, &audit_log, 0o600, "監査ログ", fix)?;
+    }
+
+    println!("{}", "秘密鍵:".bold());
+    check_keys(&mut report, &config, fix)?;
+
+    println!("{}", "設定内容:".bold());
+    if check_config(&mut report, &mut config, fix) {
+        config.save()?;
+    }
+
+    println!("{}", "外部コマンド:".bold());
+    for (program, required, usage) in [
+        ("ssh", true, "接続とリモート操作"),
+        ("scp", true, "scp バックエンド"),
+        ("rsync", false, "rsync バックエンドと除外パターン"),
+        ("ssh-keygen", false, "key コマンド"),
+    ] {
+        if argv::is_available(program) {
+            report.ok(&format!("{} が見つかりました", program));
+        } else if required {
+            report.error(&format!("{} がPATHにありません（{}に必要）", program, usage));
+        } else {
+            report.warn(&format!("{} がPATHにありません（{}に必要）", program, usage));
+        }
+    }
+
+    println!();
+    println!(
+        "診断結果: エラー {}、警告 {}、修正 {}",
+        report.errors, report.warnings, report.fixed
+    );
+    if report.errors > 0 {
+        if !fix {
+            println!("一部の問題は 'sshportal doctor --fix' で修正できます");
+        }
+        return Err(format!("{}件のエラーがあります", report.errors).into());
+    }
+    Ok(())
+}
+
+/// パーミッションがグループ・その他に許可を与えていないかを確認します
+///
+/// `expected` より厳しいパーミッション（例: 秘密鍵の0400）は問題としません。
+fn check_mode(
+    report: &mut Report,
+    path: &Path,
+    expected: u32,
+    label: &str,
+    fix: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = fs::metadata(path)?.permissions().mode() & 0o777;
+    if mode & !expected == 0 {
+        report.ok(&format!("{} {} ({:o})", label, path.display(), mode));
+    } else if fix {
+        fs::set_permissions(path, fs::Permissions::from_mode(expected))?;
+        report.fixed(&format!("{} {} を {:o} から {:o} に変更しました", label, path.display(), mode, expected));
+    } else {
+        report.error(&format!(
+            "{} {} のパーミッションが {:o} です（{:o} にしてください）",
+            label,
+            path.display(),
+            mode,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// 各ホストの秘密鍵の存在とパーミッションを確認します
+fn check_keys(report: &mut Report, config: &Config, fix: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut hosts: Vec<_> = config.hosts.iter().filter(|(_, host)| host.key_path.is_some()).collect();
+    hosts.sort_by_key(|(name, _)| name.as_str());
+    if hosts.is_empty() {
+        report.ok("秘密鍵が設定されたホストはありません");
+    }
+
+    for (name, host) in hosts {
+        let Some(ref key) = host.key_path else { continue };
+        let key = Config::expand_path(key)?;
+        let path = Path::new(&key);
+        if !path.is_file() {
+            report.error(&format!("ホスト '{}' の秘密鍵 {} が見つかりません", name, key));
+            continue;
+        }
+        check_mode(report, path, 0o600, &format!("ホスト '{}' の秘密鍵", name), fix)?;
+    }
+    Ok(())
+}
+
+/// 設定内容の不整合を確認します
+///
+/// # 戻り値
+/// 設定を修正した（保存が必要な）場合はtrue
+fn check_config(report: &mut Report, config: &mut Config, fix: bool) -> bool {
+    let mut changed = false;
+
+    // 旧形式のpathsは、優先順位が最も低いため同名のエイリアスがなければそのまま移行できる
+    let legacy = config.paths.as_ref().map_or(0, |paths| paths.len());
+    if legacy == 0 {
+        report.ok("旧形式の paths はありません");
+    } else if fix {
+        for (name, entry) in config.paths.take().unwrap_or_default() {
+            let target = if entry.is_remote { &mut config.remote_paths } else { &mut config.local_paths };
+            target.entry(name).or_insert(entry.path);
+        }
+        changed = true;
+        report.fixed(&format!("旧形式の paths の{}件を local_paths/remote_paths に移行しました", legacy));
+    } else {
+        report.warn(&format!(
+            "旧形式の paths に{}件のエントリがあります（--fix で local_paths/remote_paths に移行できます）",
+            legacy
+        ));
+    }
+
+    // 同じ接続先を持つホスト
+    let mut connections: BTreeMap<(String, u16), Vec<&str>> = BTreeMap::new();
+    for (name, host) in &config.hosts {
+        connections
+            .entry((host.connection.clone(), host.port))
+            .or_default()
+            .push(name);
+    }
+    let duplicates: Vec<_> = connections.iter().filter(|(_, names)| names.len() > 1).collect();
+    if duplicates.is_empty() {
+        report.ok("重複した接続先はありません");
+    }
+    for ((connection, port), names) in duplicates {
+        let mut names = names.clone();
+        names.sort();
+        report.warn(&format!(
+            "ホスト {} は同じ接続先 {}:{} です",
+            names.join(", "),
+            connection,
+            port
+        ));
+    }
+
+    // 未登録のホストのhost_paths（削除は行わない）
+    let mut unknown: Vec<&String> = config
+        .host_paths
+        .keys()
+        .filter(|host| !config.hosts.contains_key(*host))
+        .collect();
+    unknown.sort();
+    if unknown.is_empty() {
+        report.ok("host_paths のホストは全て登録されています");
+    }
+    for host in unknown {
+        report.warn(&format!(
+            "host_paths に未登録のホスト '{}' のパスが{}件あります",
+            host,
+            config.host_paths[host].len()
+        ));
+    }
+
+    changed
+}
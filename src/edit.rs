@@ -0,0 +1,170 @@
+// リモートファイル編集機能
+//
+// このモジュールは、リモートファイルを一時ディレクトリにダウンロードして
+// `$EDITOR` で開き、保存された内容をリモートに書き戻します。
+
+use crate::audit::{self, AuditRecord};
+use crate::checksum;
+use crate::config::Config;
+use crate::diff;
+use crate::remote;
+use crate::resolve::{self, Endpoint, Location};
+use crate::safety;
+use colored::*;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+
+/// リモートファイルをローカルのエディタで編集します
+///
+/// ファイルを権限0700の一時ディレクトリにダウンロードし、`$VISUAL`、`$EDITOR`、
+/// `vi` の順に見つかったエディタで開きます。内容が変更された場合は、
+/// 編集中にリモート側が変更されていないかをSHA-256で確認してから書き戻します。
+///
+/// # 引数
+/// * `spec` - 編集するファイル（host:エイリアスまたはhost:パス）
+/// * `sudo` - 端末を割り当てたセッションで `sudo` により読み込み、`sudo tee` で書き込むかどうか
+/// * `yes` - 保護されたホストへの書き込みの確認を省略するかどうか
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn edit_file(spec: &str, sudo: bool, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // 現在の設定を読み込み
+    let config = Config::load()?;
+
+    let method = if sudo { "ssh+sudo" } else { "ssh" };
+    let record = AuditRecord::new("edit", &[spec.to_string()], spec, method);
+    let (endpoint, path) = match resolve::resolve_spec(&config, spec) {
+        Ok(Location::Remote { endpoint, path }) => (endpoint, path),
+        Ok(Location::Local(_)) => {
+            println!("{}: 編集するファイルは host:パス の形式で指定してください", "ERROR".red());
+            return Ok(());
+        }
+        Err(e) => return audit::refused(&config, record, e.into()),
+    };
+    let location = Location::Remote { endpoint: endpoint.clone(), path: path.clone() };
+    let record = record.locations(&[], &location);
+
+    // 編集を始める前に書き込みが許可されているかを確認する
+    if let Err(e) = safety::check_write(&config, &endpoint, yes) {
+        return audit::refused(&config, record, e);
+    }
+
+    println!("{}: {}:{} を取得中...", "INFO".green(), endpoint.connection, path);
+    let original = remote::read_file(&endpoint, &path, sudo)?;
+    let original_hash = checksum::sha256_hex(&original);
+
+    // 一時ディレクトリはtempfileにより権限0700で作成され、終了時に削除される
+    let temp_dir = tempfile::Builder::new().prefix("sshportal-edit-").tempdir()?;
+    let file_name = Path::new(&path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "file".to_string());
+    let local_path = temp_dir.path().join(file_name);
+    fs::write(&local_path, &original)?;
+
+    if !open_editor(&local_path)? {
+        println!("{}: エディタが異常終了したため、アップロードを中止しました", "ERROR".red());
+        return Ok(());
+    }
+
+    let edited = fs::read(&local_path)?;
+    if checksum::sha256_hex(&edited) == original_hash {
+        println!("{}: 変更がないため、アップロードしませんでした", "INFO".green());
+        return Ok(());
+    }
+
+    // 編集中にリモートのファイルが変更されていないかを書き込みと同じセッションで確認する
+    let mut written = remote::write_file(&endpoint, &path, &edited, sudo, Some(&original_hash));
+    if let Ok(false) = written {
+        let current = remote::read_file(&endpoint, &path, sudo)?;
+        if !confirm_overwrite(&endpoint, &path, &current, &edited)? {
+            println!("{}: キャンセルされました（編集内容: {}）", "INFO".yellow(), local_path.display());
+            // 編集内容を失わないよう一時ディレクトリを残す
+            let _ = temp_dir.keep();
+            return Ok(());
+        }
+        written = remote::write_file(&endpoint, &path, &edited, sudo, None);
+    }
+    let written = written.map(|_| ()).map_err(Into::into);
+    let mut record = record.result(&written);
+    record.bytes = Some(edited.len() as u64);
+    record.checksum = Some(checksum::sha256_hex(&edited));
+    audit::record(&config, &record);
+    if written.is_err() {
+        println!("{}: アップロードに失敗しました（編集内容: {}）", "ERROR".red(), local_path.display());
+        // 編集内容を失わないよう一時ディレクトリを残す
+        let _ = temp_dir.keep();
+    }
+    written?;
+    println!("{}: {}:{} を更新しました", "SUCCESS".green(), endpoint.connection, path);
+
+    Ok(())
+}
+
+/// エディタでファイルを開きます
+///
+/// エディタ指定に引数が含まれる場合（例: `code --wait`）に対応するため、シェル経由で起動します。
+///
+/// # 戻り値
+/// エディタが正常終了した場合はtrue
+fn open_editor(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()?;
+    Ok(status.success())
+}
+
+/// 編集中にリモートが変更された場合に、上書きするかどうかを確認します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `path` - リモートファイルのパス
+/// * `current` - 現在のリモートファイルの内容
+/// * `edited` - 編集後の内容
+///
+/// # 戻り値
+/// 上書きする場合はtrue
+fn confirm_overwrite(
+    endpoint: &Endpoint,
+    path: &str,
+    current: &[u8],
+    edited: &[u8],
+) -> Result<bool, Box<dyn std::error::Error>> {
+    println!(
+        "{}: 編集中に {}:{} が変更されています",
+        "WARN".yellow(),
+        endpoint.connection,
+        path
+    );
+    loop {
+        print!("[d]差分を表示 / [o]上書き / [a]中止: ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        match answer.trim().to_lowercase().as_str() {
+            "d" | "diff" => {
+                let remote_label = format!("{}:{} (リモート)", endpoint.connection, path);
+                print!(
+                    "{}",
+                    diff::unified_diff(
+                        &String::from_utf8_lossy(current),
+                        &String::from_utf8_lossy(edited),
+                        &remote_label,
+                        "編集後"
+                    )
+                );
+            }
+            "o" | "overwrite" => return Ok(true),
+            "a" | "abort" | "" => return Ok(false),
+            _ => println!("{}: d、o、a のいずれかを入力してください", "WARN".yellow()),
+        }
+    }
+}
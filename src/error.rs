@@ -0,0 +1,86 @@
+// ライブラリのエラー型
+//
+// このモジュールは、sshportalライブラリが返すエラーを定義します。
+// ライブラリ内ではパニックせず、全ての失敗をこの型で呼び出し元に返します。
+
+use std::fmt;
+use std::io;
+
+/// sshportalライブラリのエラー
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// ホームディレクトリが見つからない
+    HomeDirNotFound,
+    /// 指定されたホストエイリアスが設定に存在しない
+    HostNotFound(String),
+    /// ホストが設定になく、有効なSSH接続文字列でもない
+    InvalidHost(String),
+    /// パス指定が不正（エイリアスの種類の不一致など）
+    InvalidPathSpec(String),
+    /// パステンプレートの展開に失敗（未定義の変数など）
+    Template(String),
+    /// 除外パターンの構文エラー
+    Pattern(String),
+    /// 指定された転送の組み合わせはサポートされていない
+    Unsupported(String),
+    /// 転送処理（SSH/SFTP）の失敗
+    Transport(String),
+    /// 転送後のチェックサムが一致しないファイル
+    ChecksumMismatch(Vec<String>),
+    /// 入出力エラー
+    Io(io::Error),
+    /// 設定ファイルのJSON解析エラー
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::HomeDirNotFound => write!(f, "ホームディレクトリが見つかりません"),
+            Error::HostNotFound(name) => write!(f, "ホスト '{}' が見つかりません", name),
+            Error::InvalidHost(host) => write!(
+                f,
+                "ホスト '{}' が見つからず、有効なSSH接続文字列でもありません",
+                host
+            ),
+            Error::InvalidPathSpec(msg) => write!(f, "{}", msg),
+            Error::Template(msg) => write!(f, "{}", msg),
+            Error::Pattern(msg) => write!(f, "無効なパターンです: {}", msg),
+            Error::Unsupported(msg) => write!(f, "{}", msg),
+            Error::Transport(msg) => write!(f, "{}", msg),
+            Error::ChecksumMismatch(files) => write!(
+                f,
+                "チェックサムが一致しません: {}",
+                files.join(", ")
+            ),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "設定ファイルの解析に失敗しました: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// sshportalライブラリの結果型
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,167 @@
+// 除外パターン機能
+//
+// このモジュールは、ディレクトリ内のパスを `.gitignore` 形式のパターンで
+// 除外するかどうかの判定と、転送時の除外・包含パターンの管理を行います。
+
+use crate::error::{Error, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// 常に除外するパターン
+const ALWAYS_IGNORED: [&str; 1] = [".git/"];
+
+/// コピー元のルートに置く除外パターンファイルの名前
+pub const IGNORE_FILE: &str = ".sshportalignore";
+
+/// ディレクトリを基準にした除外パターンの集合
+pub struct PathFilter {
+    gitignore: Gitignore,
+}
+
+impl PathFilter {
+    /// 基準ディレクトリの `.gitignore`・`.sshportalignore` と追加のパターンから
+    /// 除外パターンを作成します
+    ///
+    /// `.git` ディレクトリは常に除外します。
+    ///
+    /// # 引数
+    /// * `root` - 基準ディレクトリ
+    /// * `patterns` - 追加の除外パターン（`.gitignore` と同じ書式）
+    ///
+    /// # 戻り値
+    /// 除外パターン、またはパターンの構文エラー
+    pub fn new(root: &Path, patterns: &[String]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in ALWAYS_IGNORED {
+            add_line(&mut builder, pattern)?;
+        }
+
+        for file in [".gitignore", IGNORE_FILE] {
+            let file = root.join(file);
+            if file.is_file() {
+                if let Some(e) = builder.add(&file) {
+                    return Err(Error::Pattern(e.to_string()));
+                }
+            }
+        }
+
+        for pattern in patterns {
+            add_line(&mut builder, pattern)?;
+        }
+
+        let gitignore = builder.build().map_err(|e| Error::Pattern(e.to_string()))?;
+        Ok(PathFilter { gitignore })
+    }
+
+    /// パターンのみから除外パターンを作成します
+    ///
+    /// `is_ignored` には基準ディレクトリからの相対パスを渡します。
+    ///
+    /// # 引数
+    /// * `patterns` - 除外パターン（`.gitignore` と同じ書式）
+    pub fn from_patterns(patterns: &[String]) -> Result<Self> {
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in patterns {
+            add_line(&mut builder, pattern)?;
+        }
+        let gitignore = builder.build().map_err(|e| Error::Pattern(e.to_string()))?;
+        Ok(PathFilter { gitignore })
+    }
+
+    /// パスが除外対象かどうかを返します
+    ///
+    /// 親ディレクトリが除外対象の場合も除外します。
+    ///
+    /// # 引数
+    /// * `path` - 判定するパス（基準ディレクトリ配下）
+    /// * `is_dir` - パスがディレクトリかどうか
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        path.starts_with(self.gitignore.path())
+            && self
+                .gitignore
+                .matched_path_or_any_parents(path, is_dir)
+                .is_ignore()
+    }
+}
+
+/// 除外パターンを1行追加します
+fn add_line(builder: &mut GitignoreBuilder, pattern: &str) -> Result<()> {
+    builder
+        .add_line(None, pattern)
+        .map(|_| ())
+        .map_err(|e| Error::Pattern(e.to_string()))
+}
+
+/// 転送時の除外・包含パターン
+///
+/// パターンはrsyncの `--exclude`/`--include` と同じ書式で、包含パターンは除外パターンより優先されます。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferFilter {
+    /// 除外パターンに一致しても転送するパターン
+    pub include: Vec<String>,
+    /// 転送から除外するパターン
+    pub exclude: Vec<String>,
+}
+
+impl TransferFilter {
+    /// `.sshportalignore` の内容を解析します
+    ///
+    /// 空行と `#` で始まる行は無視し、`!` で始まる行は包含パターンとして扱います。
+    ///
+    /// # 引数
+    /// * `content` - ファイルの内容
+    pub fn parse_ignore_file(content: &str) -> Self {
+        let mut filter = TransferFilter::default();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix('!') {
+                Some(pattern) => filter.include.push(pattern.to_string()),
+                None => filter.exclude.push(line.to_string()),
+            }
+        }
+        filter
+    }
+
+    /// パターンが1つもないかどうかを返します
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// 別のパターンを追加します（重複は追加しません）
+    pub fn extend(&mut self, other: TransferFilter) {
+        for pattern in other.include {
+            if !self.include.contains(&pattern) {
+                self.include.push(pattern);
+            }
+        }
+        for pattern in other.exclude {
+            if !self.exclude.contains(&pattern) {
+                self.exclude.push(pattern);
+            }
+        }
+    }
+
+    /// コピー元のルートからの相対パスに適用する除外パターンを作成します
+    ///
+    /// 包含パターンは除外パターンより優先されます。
+    pub fn matcher(&self) -> Result<PathFilter> {
+        let patterns: Vec<String> = self
+            .exclude
+            .iter()
+            .cloned()
+            .chain(self.include.iter().map(|p| format!("!{}", p)))
+            .collect();
+        PathFilter::from_patterns(&patterns)
+    }
+
+    /// rsyncに渡すフィルタ引数を返します
+    ///
+    /// rsyncは最初に一致したパターンを使用するため、包含パターンを先に並べます。
+    pub fn rsync_args(&self) -> Vec<String> {
+        let include = self.include.iter().map(|p| format!("--include={}", p));
+        let exclude = self.exclude.iter().map(|p| format!("--exclude={}", p));
+        include.chain(exclude).collect()
+    }
+}
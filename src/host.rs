@@ -3,7 +3,11 @@
 // このモジュールは、SSH接続先ホストの追加、削除、一覧表示、
 // および接続を行う機能を提供します。
 
-use crate::config::{Config, Host};
+use crate::argv;
+use crate::config::{Config, Host, SafetyLevel};
+use crate::hostkey;
+use crate::resolve;
+use crate::safety;
 use colored::*;
 use std::io::{self, Write};
 
@@ -32,9 +36,8 @@ pub fn add_host(name: &str, connection: &str, port: u16, key_path: Option<&str>)
 
     // 新しいホスト情報を作成
     let host = Host {
-        connection: connection.to_string(),
-        port,
-        key_path: key_path.map(|k| Config::expand_path(k)),
+        key_path: key_path.map(Config::expand_path).transpose()?,
+        ..Host::new(connection, port)
     };
 
     // 設定にホストを追加し、保存
@@ -73,6 +76,131 @@ pub fn remove_host(name: &str) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// ホストのデフォルトのアップロード先を設定します
+/// 
+/// `push` でホストのみを指定した場合に使用されるパスエイリアスまたはパスを設定します。
+/// 
+/// # 引数
+/// * `name` - ホストのエイリアス名
+/// * `upload` - アップロード先（Noneの場合は設定を解除）
+/// 
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn set_default_upload(name: &str, upload: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+
+    let Some(host) = config.hosts.get_mut(name) else {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    };
+    host.default_upload = upload.map(str::to_string);
+    config.save()?;
+
+    match upload {
+        Some(upload) => println!("{}: ホスト '{}' のアップロード先を '{}' に設定しました", "INFO".green(), name, upload),
+        None => println!("{}: ホスト '{}' のアップロード先を解除しました", "INFO".green(), name),
+    }
+    Ok(())
+}
+
+/// ホストの転送設定（帯域制限・圧縮・暗号方式）を変更します
+/// 
+/// 指定された項目のみを変更します。帯域制限の0と空の暗号方式は設定の解除を意味します。
+/// コピー時のコマンドラインオプションはこの設定より優先されます。
+/// 
+/// # 引数
+/// * `name` - ホストのエイリアス名
+/// * `bwlimit` - 帯域制限（KiB/s）
+/// * `compression` - SSHの圧縮を使用するかどうか
+/// * `cipher` - SSHの暗号方式
+/// * `clear` - 全ての転送設定を解除するかどうか
+/// 
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn set_transfer(
+    name: &str,
+    bwlimit: Option<u32>,
+    compression: Option<bool>,
+    cipher: Option<&str>,
+    clear: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+
+    let Some(host) = config.hosts.get_mut(name) else {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    };
+    if clear {
+        host.bwlimit = None;
+        host.compression = None;
+        host.cipher = None;
+    }
+    if let Some(limit) = bwlimit {
+        host.bwlimit = (limit > 0).then_some(limit);
+    }
+    if let Some(compression) = compression {
+        host.compression = Some(compression);
+    }
+    if let Some(cipher) = cipher {
+        host.cipher = (!cipher.is_empty()).then(|| cipher.to_string());
+    }
+    let summary = transfer_info(host);
+    config.save()?;
+
+    match summary.as_str() {
+        "" => println!("{}: ホスト '{}' の転送設定を解除しました", "INFO".green(), name),
+        summary => println!("{}: ホスト '{}' の転送設定を更新しました{}", "INFO".green(), name, summary),
+    }
+    Ok(())
+}
+
+/// ホストの安全レベルを設定します
+/// 
+/// 保護されたホスト（protected）への書き込みにはホスト名の入力による確認が必要になり、
+/// 読み取り専用のホスト（read-only）への書き込みは拒否されます。
+/// 
+/// # 引数
+/// * `name` - ホストのエイリアス名
+/// * `level` - 安全レベル
+/// 
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn set_safety(name: &str, level: SafetyLevel) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+
+    let Some(host) = config.hosts.get_mut(name) else {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    };
+    host.safety = level;
+    config.save()?;
+
+    let label = match level {
+        SafetyLevel::Normal => "制限なし",
+        SafetyLevel::Protected => "保護（書き込み時に確認）",
+        SafetyLevel::ReadOnly => "読み取り専用",
+    };
+    println!("{}: ホスト '{}' の安全レベルを{}に設定しました", "INFO".green(), name, label);
+    Ok(())
+}
+
+/// ホストの転送設定を一覧表示用の文字列にします
+fn transfer_info(host: &Host) -> String {
+    let mut info = String::new();
+    if let Some(limit) = host.bwlimit {
+        info.push_str(&format!(" (bwlimit: {} KiB/s)", limit));
+    }
+    match host.compression {
+        Some(true) => info.push_str(" (compression)"),
+        Some(false) => info.push_str(" (no compression)"),
+        None => {}
+    }
+    if let Some(ref cipher) = host.cipher {
+        info.push_str(&format!(" (cipher: {})", cipher));
+    }
+    info
+}
+
 /// 設定されているホストの一覧を表示します
 /// 
 /// 全ての設定済みホストを名前、接続文字列、ポート番号と共に表示します。
@@ -93,12 +221,21 @@ pub fn list_hosts() -> Result<(), Box<dyn std::error::Error>> {
     // ホスト一覧を表示
     println!("{}", "設定済みホスト:".bold());
     for (name, host) in &config.hosts {
-        let key_info = if let Some(ref key) = host.key_path {
+        let mut key_info = if let Some(ref key) = host.key_path {
             format!(" (key: {})", key)
         } else {
             String::new()
         };
-        println!("  {} -> {}:{}{}", name.cyan(), host.connection, host.port, key_info.dimmed());
+        if let Some(ref upload) = host.default_upload {
+            key_info.push_str(&format!(" (upload: {})", upload));
+        }
+        key_info.push_str(&transfer_info(host));
+        let safety = match host.safety {
+            SafetyLevel::Normal => "".normal(),
+            SafetyLevel::Protected => " [protected]".red().bold(),
+            SafetyLevel::ReadOnly => " [read-only]".yellow().bold(),
+        };
+        println!("  {} -> {}:{}{}{}", name.cyan(), host.connection, host.port, safety, key_info.dimmed());
     }
 
     Ok(())
@@ -116,33 +253,28 @@ pub fn list_hosts() -> Result<(), Box<dyn std::error::Error>> {
 /// 成功時は()、失敗時はエラーを返します。
 pub fn connect_host(name: &str) -> Result<(), Box<dyn std::error::Error>> {
     // 現在の設定を読み込み
-    let config = Config::load()?;
+    let mut config = Config::load()?;
 
     // 指定されたホストを検索
-    let host = match config.hosts.get(name) {
-        Some(host) => host,
-        None => {
-            println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
-            println!("利用可能なホストを確認するには 'sshportal list-hosts' を使用してください");
-            return Ok(());
-        }
-    };
+    if !config.hosts.contains_key(name) {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        println!("利用可能なホストを確認するには 'sshportal list-hosts' を使用してください");
+        return Ok(());
+    }
+    let endpoint = resolve::resolve_host(&config, name)?;
 
+    safety::print_banner(&config, &endpoint);
     println!("{}: ホスト '{}' に接続中...", "INFO".blue(), name);
     
     // SSH接続コマンドを実行
-    let mut cmd = std::process::Command::new("ssh");
-    cmd.arg(&host.connection)
-        .arg("-p")
-        .arg(&host.port.to_string());
-    
-    // 秘密鍵が指定されている場合は追加
-    if let Some(ref key_path) = host.key_path {
-        cmd.arg("-i").arg(key_path);
-    }
-    
-    cmd.status()?;
+    let status = argv::command(&argv::ssh_argv(&endpoint)).status()?;
 
+    // 終了コード255はssh自体のエラー（接続・認証・ホスト鍵の検証の失敗）
+    if status.code() == Some(255) {
+        hostkey::explain_failure(&config, name, &endpoint);
+    } else {
+        hostkey::record_first_connect(&mut config, name, &endpoint);
+    }
     Ok(())
 }
 
@@ -0,0 +1,498 @@
+// ホスト鍵の記録と検証機能
+//
+// このモジュールは、sshが検証してknown_hostsに保存したホスト鍵の指紋をホストの設定に記録し、
+// サーバーの再構築などでホスト鍵が変わった場合に記録との違いと対処方法を表示します。
+// ssh-keyscanで取得した鍵は認証されていないため、記録との比較と、確認を経たrefreshにのみ使用します。
+// 設定の `known_hosts` が指定されている場合は、そのファイルのエントリも更新します。
+// ssh-keyscanはssh_configを読まないため、接続先は `ssh -G` でsshと同じように解決します。
+
+use crate::config::Config;
+use crate::resolve::{self, Endpoint};
+use colored::*;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// ssh-keyscanまたはknown_hostsから取得したホスト鍵
+struct HostKey {
+    /// known_hosts形式の行
+    line: String,
+    /// 記録用の指紋（例: "ED25519 SHA256:..."）
+    fingerprint: String,
+}
+
+/// 記録する鍵の種類の優先順
+const PREFERRED_TYPES: [&str; 3] = ["ED25519", "ECDSA", "RSA"];
+
+/// ssh_configを適用した実際の接続先
+struct Target {
+    /// 実際に接続するホスト名（ssh_configの `HostName`）
+    hostname: String,
+    /// ポート番号
+    port: u16,
+    /// known_hostsで使用する名前（ssh_configの `HostKeyAlias`）
+    host_key_alias: Option<String>,
+    /// `ProxyJump` または `ProxyCommand` を経由するかどうか
+    proxied: bool,
+}
+
+impl Target {
+    /// `ssh -G` でssh_configを適用した接続先を求めます
+    ///
+    /// `ssh -G` を実行できない場合は、接続文字列のホスト名とポートをそのまま使用します。
+    fn resolve(endpoint: &Endpoint) -> Self {
+        let output = Command::new("ssh")
+            .args(["-G", "-p", &endpoint.port.to_string(), "--", &endpoint.connection])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => Self::parse(&String::from_utf8_lossy(&output.stdout), endpoint),
+            _ => Self::parse("", endpoint),
+        }
+    }
+
+    /// `ssh -G` の出力（`キーワード 値` の行）から接続先を求めます
+    fn parse(text: &str, endpoint: &Endpoint) -> Self {
+        let mut target = Target {
+            hostname: endpoint
+                .connection
+                .rsplit_once('@')
+                .map_or(endpoint.connection.as_str(), |(_, host)| host)
+                .to_string(),
+            port: endpoint.port,
+            host_key_alias: None,
+            proxied: false,
+        };
+        for line in text.lines() {
+            let Some((keyword, value)) = line.trim().split_once(' ') else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() || value == "none" {
+                continue;
+            }
+            match keyword.to_lowercase().as_str() {
+                "hostname" => target.hostname = value.to_string(),
+                "port" => target.port = value.parse().unwrap_or(target.port),
+                "hostkeyalias" => target.host_key_alias = Some(value.to_string()),
+                "proxyjump" | "proxycommand" => target.proxied = true,
+                _ => {}
+            }
+        }
+        target
+    }
+
+    /// known_hostsでのホストの表記を返します
+    ///
+    /// `HostKeyAlias` がある場合はその名前、ポート22以外は `[host]:port` です。
+    fn pattern(&self) -> String {
+        match (&self.host_key_alias, self.port) {
+            (Some(alias), _) => alias.clone(),
+            (None, 22) => self.hostname.clone(),
+            (None, port) => format!("[{}]:{}", self.hostname, port),
+        }
+    }
+}
+
+/// 記録済みのホスト鍵の指紋とknown_hostsのエントリを表示します
+///
+/// # 引数
+/// * `name` - ホストのエイリアス名
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn show(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+    let Some(host) = config.hosts.get(name) else {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    };
+    let endpoint = resolve::resolve_host(&config, name)?;
+    let pattern = Target::resolve(&endpoint).pattern();
+
+    println!("{} ({})", name.cyan(), pattern);
+    match host.host_key {
+        Some(ref fingerprint) => println!("  記録済みの指紋: {}", fingerprint),
+        None => println!("  記録済みの指紋: {}", "なし".dimmed()),
+    }
+
+    if let Some(ref file) = endpoint.known_hosts {
+        let entries = entries_for(file, &pattern);
+        println!("  known_hosts: {}（{}件）", file, entries.len());
+        for line in entries {
+            match fingerprint_of(&line) {
+                Some(fingerprint) => println!("    {}", fingerprint),
+                None => println!("    {}", line.dimmed()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ホスト鍵を取得し直して記録を更新します
+///
+/// ssh-keyscanで取得した鍵は認証されていないため、記録が変わる場合は新旧の指紋を表示し、
+/// 確認を得てから（または `--yes` の場合に）更新します。
+/// 設定の `known_hosts` が指定されている場合は、そのファイルのこのホストの
+/// エントリも取得した鍵で置き換えます。
+///
+/// # 引数
+/// * `name` - ホストのエイリアス名
+/// * `yes` - 確認を省略するかどうか（`--yes`）
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn refresh(name: &str, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+    let Some(host) = config.hosts.get(name) else {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    };
+    let previous = host.host_key.clone();
+    let endpoint = resolve::resolve_host(&config, name)?;
+    let target = Target::resolve(&endpoint);
+
+    let keys = scan(&target)?;
+    let fingerprint = preferred(&keys).fingerprint.clone();
+    let lines: Vec<&str> = keys.iter().map(|key| key.line.as_str()).collect();
+    let pattern = target.pattern();
+    let entries_changed = endpoint.known_hosts.as_ref().is_some_and(|file| {
+        let mut current = entries_for(file, &pattern);
+        let mut scanned: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        current.sort();
+        scanned.sort();
+        current != scanned
+    });
+
+    if previous.as_deref() == Some(fingerprint.as_str()) && !entries_changed {
+        println!("{}: ホスト '{}' のホスト鍵は変わっていません: {}", "INFO".green(), name, fingerprint);
+        return Ok(());
+    }
+
+    println!("ホスト '{}' ({}) のホスト鍵:", name.cyan(), pattern);
+    println!("  旧: {}", previous.as_deref().unwrap_or("なし").red());
+    println!("  新: {}", fingerprint.green());
+    if !yes && !confirm_replace()? {
+        println!("{}: キャンセルされました", "INFO".yellow());
+        return Ok(());
+    }
+
+    if let Some(host) = config.hosts.get_mut(name) {
+        host.host_key = Some(fingerprint.clone());
+    }
+    config.save()?;
+    println!("{}: ホスト '{}' のホスト鍵の記録を更新しました", "SUCCESS".green(), name);
+
+    if let Some(ref file) = endpoint.known_hosts {
+        replace_entries(file, &pattern, &lines)?;
+        println!("{}: {} のエントリを{}件更新しました", "INFO".green(), file, lines.len());
+    }
+    Ok(())
+}
+
+/// sshportal専用のknown_hostsファイルを表示または設定します
+///
+/// 設定したファイルはssh・scp・rsync（およびネイティブバックエンド）のホスト鍵の検証に使用し、
+/// `refresh` はこのファイルのエントリも置き換えます。
+///
+/// # 引数
+/// * `path` - known_hostsファイルのパス（Noneの場合は現在の設定を表示）
+/// * `clear` - 設定を解除するかどうか
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn set_known_hosts(path: Option<&str>, clear: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+    if clear {
+        config.known_hosts = None;
+        config.save()?;
+        println!("{}: known_hosts の設定を解除しました（sshの既定のファイルを使用します）", "INFO".green());
+        return Ok(());
+    }
+    let Some(path) = path else {
+        match config.known_hosts {
+            Some(ref file) => println!("known_hosts: {}", file),
+            None => println!("known_hosts: {}", "未設定（sshの既定のファイル）".dimmed()),
+        }
+        return Ok(());
+    };
+
+    if !std::path::Path::new(&Config::expand_path(path)?).exists() {
+        println!(
+            "{}: '{}' はまだ存在しません（'sshportal hostkey refresh <host>' でエントリを追加できます）",
+            "WARN".yellow(),
+            path
+        );
+    }
+    config.known_hosts = Some(path.to_string());
+    config.save()?;
+    println!("{}: known_hosts を {} に設定しました", "INFO".green(), path);
+    Ok(())
+}
+
+/// ホスト鍵の記録を置き換えるかどうかを確認します
+///
+/// # 戻り値
+/// 置き換える場合はtrue。標準入力が端末でない場合はエラー
+fn confirm_replace() -> Result<bool, Box<dyn std::error::Error>> {
+    if !io::stdin().is_terminal() {
+        return Err("ホスト鍵の記録を置き換えるには端末での確認か --yes が必要です".into());
+    }
+    println!("管理者などから入手した正しい指紋と一致することを確認してください。");
+    print!("ホスト鍵の記録を置き換えますか？ [y/N]: ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// 現在のホスト鍵が記録と一致するかを確認します
+///
+/// # 引数
+/// * `name` - ホストのエイリアス名
+///
+/// # 戻り値
+/// 一致する場合は()、一致しない場合や記録がない場合はエラーを返します。
+pub fn verify(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+    let Some(host) = config.hosts.get(name) else {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    };
+    let Some(ref recorded) = host.host_key else {
+        return Err(format!(
+            "ホスト '{}' のホスト鍵は記録されていません。'sshportal hostkey refresh {}' で記録してください",
+            name, name
+        )
+        .into());
+    };
+    let endpoint = resolve::resolve_host(&config, name)?;
+    let target = Target::resolve(&endpoint);
+
+    let keys = scan(&target)?;
+    if keys.iter().any(|key| key.fingerprint == *recorded) {
+        println!("{}: ホスト '{}' のホスト鍵は記録と一致しています: {}", "SUCCESS".green(), name, recorded);
+        return Ok(());
+    }
+    print_mismatch(name, &endpoint, &target, recorded, &preferred(&keys).fingerprint);
+    Err(format!("ホスト '{}' のホスト鍵が記録と一致しません", name).into())
+}
+
+/// 初めて接続に成功したホストのホスト鍵を記録します
+///
+/// sshが検証してknown_hostsに保存した鍵を `ssh-keygen -F` で読み出して記録します
+/// （別の接続で取得し直すと、その接続を改ざんされた場合に偽の鍵を記録してしまうため）。
+/// 記録済みの場合や取得に失敗した場合は何もしません（接続自体は完了しているため）。
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `name` - ホストのエイリアス名
+/// * `endpoint` - 接続先
+pub fn record_first_connect(config: &mut Config, name: &str, endpoint: &Endpoint) {
+    if config.hosts.get(name).is_none_or(|host| host.host_key.is_some()) {
+        return;
+    }
+    let keys = accepted(endpoint, &Target::resolve(endpoint));
+    if keys.is_empty() {
+        return;
+    }
+    let fingerprint = preferred(&keys).fingerprint.clone();
+    if let Some(host) = config.hosts.get_mut(name) {
+        host.host_key = Some(fingerprint.clone());
+    }
+    match config.save() {
+        Ok(()) => println!("{}: ホスト '{}' のホスト鍵を記録しました: {}", "INFO".green(), name, fingerprint),
+        Err(e) => println!("{}: ホスト鍵を記録できませんでした: {}", "WARN".yellow(), e),
+    }
+}
+
+/// 接続に失敗したホストのホスト鍵が記録と異なる場合に、原因と対処方法を表示します
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `name` - ホストのエイリアス名
+/// * `endpoint` - 接続先
+pub fn explain_failure(config: &Config, name: &str, endpoint: &Endpoint) {
+    let Some(recorded) = config.hosts.get(name).and_then(|host| host.host_key.as_ref()) else {
+        return;
+    };
+    let target = Target::resolve(endpoint);
+    let Ok(keys) = scan(&target) else { return };
+    if !keys.iter().any(|key| key.fingerprint == *recorded) {
+        print_mismatch(name, endpoint, &target, recorded, &preferred(&keys).fingerprint);
+    }
+}
+
+/// ホスト鍵が記録と異なる場合の説明を表示します
+fn print_mismatch(name: &str, endpoint: &Endpoint, target: &Target, recorded: &str, current: &str) {
+    println!("{}: ホスト '{}' のホスト鍵が記録と異なります", "ERROR".red(), name);
+    println!("  記録: {}", recorded.green());
+    println!("  現在: {}", current.red());
+    println!("サーバーの再構築やOSの再インストールでホスト鍵が変わった可能性がありますが、");
+    println!("中間者攻撃の可能性もあります。管理者に新しい指紋を確認してから、次を実行してください:");
+    // sshportal専用のknown_hostsはrefreshでエントリを置き換えるため、削除は不要
+    if endpoint.known_hosts.is_none() {
+        println!("  ssh-keygen -R '{}'", target.pattern());
+    }
+    println!("  sshportal hostkey refresh {}", name);
+}
+
+/// sshが検証してknown_hostsに保存したホスト鍵を取得します
+///
+/// 設定の `known_hosts` が指定されている場合はそのファイル、それ以外は
+/// `~/.ssh/known_hosts` を `ssh-keygen -F` で検索します（ハッシュ化されたエントリにも対応）。
+///
+/// # 戻り値
+/// 保存されているホスト鍵（見つからない場合は空）
+fn accepted(endpoint: &Endpoint, target: &Target) -> Vec<HostKey> {
+    let mut command = Command::new("ssh-keygen");
+    command.args(["-F", &target.pattern()]);
+    if let Some(ref file) = endpoint.known_hosts {
+        command.args(["-f", file]);
+    }
+    let Ok(output) = command.stderr(Stdio::null()).output() else {
+        return Vec::new();
+    };
+    parse_keys(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// known_hosts形式の出力からホスト鍵を取り出します（コメント行は無視します）
+fn parse_keys(text: &str) -> Vec<HostKey> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            Some(HostKey {
+                line: line.to_string(),
+                fingerprint: fingerprint_of(line)?,
+            })
+        })
+        .collect()
+}
+
+/// ssh-keyscanでホスト鍵を取得します
+///
+/// 取得した鍵は認証されていないため、記録との比較と確認を経たrefreshにのみ使用します。
+/// ssh-keyscanはプロキシを経由できないため、`ProxyJump`・`ProxyCommand` を使うホストはエラーにします。
+/// 取得した行のホスト名はknown_hostsでの表記（`HostKeyAlias` など）に置き換えます。
+fn scan(target: &Target) -> Result<Vec<HostKey>, Box<dyn std::error::Error>> {
+    let pattern = target.pattern();
+    if target.proxied {
+        return Err(format!(
+            "'{}' はssh_configでProxyJumpまたはProxyCommandを使用しているため、ssh-keyscanでホスト鍵を取得できません",
+            pattern
+        )
+        .into());
+    }
+    let output = Command::new("ssh-keyscan")
+        .args(["-p", &target.port.to_string(), "--", &target.hostname])
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("ssh-keyscan を実行できません: {}", e))?;
+
+    let text: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(_, key)| format!("{} {}", pattern, key))
+        .collect();
+    let keys = parse_keys(&text.join("\n"));
+    if keys.is_empty() {
+        return Err(format!("'{}' のホスト鍵を取得できませんでした", pattern).into());
+    }
+    Ok(keys)
+}
+
+/// 記録に使う鍵を優先順（ED25519、ECDSA、RSA）で選びます
+fn preferred(keys: &[HostKey]) -> &HostKey {
+    PREFERRED_TYPES
+        .iter()
+        .find_map(|kind| keys.iter().find(|key| key.fingerprint.starts_with(kind)))
+        .unwrap_or(&keys[0])
+}
+
+/// known_hosts形式の行の指紋を `ssh-keygen -lf -` で求めます
+///
+/// # 戻り値
+/// "ED25519 SHA256:..." 形式の指紋。求められない場合はNone
+fn fingerprint_of(line: &str) -> Option<String> {
+    let mut child = Command::new("ssh-keygen")
+        .args(["-l", "-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(format!("{}\n", line).as_bytes()).ok()?;
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // 出力形式: "256 SHA256:xxxx host (ED25519)"
+    let text = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let hash = fields.get(1)?;
+    let kind = fields.last()?.trim_start_matches('(').trim_end_matches(')');
+    Some(format!("{} {}", kind, hash))
+}
+
+/// known_hostsの行がホストのエントリかどうか
+///
+/// ハッシュ化されたエントリ（`|1|...`）は判定できないため一致しないものとします。
+fn matches_host(line: &str, pattern: &str) -> bool {
+    line.split_whitespace()
+        .next()
+        .is_some_and(|hosts| hosts.split(',').any(|host| host == pattern))
+}
+
+/// known_hostsファイルからホストのエントリを返します
+fn entries_for(file: &str, pattern: &str) -> Vec<String> {
+    fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| matches_host(line, pattern))
+        .map(str::to_string)
+        .collect()
+}
+
+/// known_hostsファイルのホストのエントリを置き換えます
+fn replace_entries(file: &str, pattern: &str, lines: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(file).unwrap_or_default();
+    let mut kept: Vec<&str> = content.lines().filter(|line| !matches_host(line, pattern)).collect();
+    kept.extend(lines);
+    if let Some(dir) = std::path::Path::new(file).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, format!("{}\n", kept.join("\n")))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_applies_ssh_config_output() {
+        let endpoint = Endpoint::new("deploy@prod", 22);
+
+        let target = Target::parse("user deploy\nhostname 10.0.0.5\nport 2200\nproxycommand none\n", &endpoint);
+        assert_eq!(target.hostname, "10.0.0.5");
+        assert!(!target.proxied);
+        assert_eq!(target.pattern(), "[10.0.0.5]:2200");
+
+        let target = Target::parse("hostname 10.0.0.5\nport 22\nhostkeyalias prod-key\n", &endpoint);
+        assert_eq!(target.pattern(), "prod-key");
+
+        assert!(Target::parse("hostname internal\nproxyjump bastion\n", &endpoint).proxied);
+    }
+
+    #[test]
+    fn target_falls_back_to_connection_string() {
+        let target = Target::parse("", &Endpoint::new("deploy@prod.example.com", 2222));
+        assert_eq!(target.pattern(), "[prod.example.com]:2222");
+        assert_eq!(Target::parse("", &Endpoint::new("prod", 22)).pattern(), "prod");
+    }
+}
@@ -0,0 +1,612 @@
+// バックグラウンドジョブ機能
+//
+// このモジュールは、転送コマンドをローカルのキューに登録し、切り離された
+// ワーカープロセスで同時実行数の上限まで実行します。ジョブの状態と出力は
+// 設定ディレクトリの `jobs/` に保存されるため、シェルを再起動しても結果を確認できます。
+
+use crate::config::Config;
+use crate::safety;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// 同時実行数の既定値
+const DEFAULT_MAX_JOBS: usize = 2;
+/// ワーカーがキューと実行中のジョブを確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// 状態ファイルのロックを待つ最大時間（超えた場合は異常終了したプロセスのロックとみなす）
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ジョブの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JobStatus {
+    /// 実行待ち
+    Queued,
+    /// 実行中
+    Running,
+    /// 正常終了
+    Succeeded,
+    /// 異常終了
+    Failed,
+    /// キャンセル済み
+    Cancelled,
+}
+
+impl JobStatus {
+    /// 一覧表示用の色付きラベルを返します（全角3文字分の幅に揃える）
+    fn label(&self) -> ColoredString {
+        match self {
+            JobStatus::Queued => "待機中".normal(),
+            JobStatus::Running => "実行中".cyan(),
+            JobStatus::Succeeded => "完了  ".green(),
+            JobStatus::Failed => "失敗  ".red(),
+            JobStatus::Cancelled => "取消  ".yellow(),
+        }
+    }
+
+    /// 終了した状態かどうか
+    fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+/// キューに登録されたジョブ
+#[derive(Debug, Serialize, Deserialize)]
+struct Job {
+    /// ジョブ番号
+    id: u64,
+    /// 実行するsshportalの引数（`--background` を除く）
+    args: Vec<String>,
+    /// 登録時のカレントディレクトリ（相対パスの基準）
+    cwd: String,
+    /// 現在の状態
+    status: JobStatus,
+    /// 登録日時
+    created_at: String,
+    /// 開始日時
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started_at: Option<String>,
+    /// 終了日時
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    finished_at: Option<String>,
+    /// 実行中のプロセスID（プロセスグループIDを兼ねる）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    /// 終了コード
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    /// 登録時に保護されたホストへの書き込みが確認済みかどうか
+    #[serde(default)]
+    confirmed: bool,
+}
+
+/// 現在のコマンドを `--background` を除いてキューに登録します
+///
+/// 登録後、ワーカーが動いていなければ切り離して起動します。
+/// ジョブは端末なしで実行されるため、保護されたホストへの書き込みは登録時に確認しておき、
+/// 確認済みのジョブは `--yes` を付けて実行します。
+///
+/// # 引数
+/// * `confirmed` - 保護されたホストへの書き込みを登録時に確認したかどうか
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn enqueue_current(confirmed: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let args = job_args(std::env::args().skip(1).collect(), confirmed);
+    let dir = jobs_dir()?;
+    fs::create_dir_all(&dir)?;
+
+    // 番号の重複を避けるため、ファイルを排他的に作成できた番号を使う
+    let mut id = load_jobs()?.last().map(|job| job.id + 1).unwrap_or(1);
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(job_file(id)?) {
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => id += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let job = Job {
+        id,
+        args,
+        cwd: std::env::current_dir()?.to_string_lossy().to_string(),
+        status: JobStatus::Queued,
+        created_at: now(),
+        started_at: None,
+        finished_at: None,
+        pid: None,
+        exit_code: None,
+        confirmed,
+    };
+    save_job(&job)?;
+    println!(
+        "{}: ジョブ #{} をキューに登録しました（出力: sshportal jobs log {}）",
+        "INFO".green(),
+        id,
+        id
+    );
+
+    start_worker()
+}
+
+/// コマンドラインの引数からジョブとして実行する引数を作成します
+///
+/// `--` より前にある最初の `--background` だけを取り除きます（`--` 以降はパスとして扱われるため
+/// 変更しません）。確認済みの場合は `--yes` を `--` より前に追加します。
+fn job_args(mut args: Vec<String>, confirmed: bool) -> Vec<String> {
+    let options_end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+    if let Some(index) = args[..options_end].iter().position(|a| a == "--background") {
+        args.remove(index);
+    }
+    let options_end = args.iter().position(|a| a == "--").unwrap_or(args.len());
+    if confirmed && !args[..options_end].iter().any(|a| a == "--yes" || a == "-y") {
+        args.insert(options_end, "--yes".to_string());
+    }
+    args
+}
+
+/// ジョブの一覧を表示します
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn list_jobs() -> Result<(), Box<dyn std::error::Error>> {
+    let jobs = load_jobs()?;
+    if jobs.is_empty() {
+        println!("ジョブはありません");
+        return Ok(());
+    }
+
+    println!("{}", "ジョブ:".bold());
+    for job in &jobs {
+        let mut info = job.created_at.clone();
+        if let Some(ref finished) = job.finished_at {
+            info.push_str(&format!(" → {}", finished));
+        }
+        if let (JobStatus::Failed, Some(code)) = (job.status, job.exit_code) {
+            info.push_str(&format!(" (終了コード {})", code));
+        }
+        println!(
+            "  {:>4}  {}  sshportal {}  {}",
+            format!("#{}", job.id).cyan(),
+            job.status.label(),
+            job.args.join(" "),
+            info.dimmed()
+        );
+    }
+    println!("同時実行数: {}", max_jobs());
+    Ok(())
+}
+
+/// ジョブの出力を表示します
+///
+/// # 引数
+/// * `id` - ジョブ番号
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn show_log(id: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(job) = find_job(id)? else {
+        println!("{}: ジョブ #{} が見つかりません", "ERROR".red(), id);
+        return Ok(());
+    };
+    match fs::read(log_file(id)?) {
+        Ok(log) => io::stdout().write_all(&log)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("{}: ジョブ #{} の出力はまだありません（{}）", "INFO".green(), id, job.status.label().clear());
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// ジョブをキャンセルします
+///
+/// 待機中のジョブは実行されなくなり、実行中のジョブはプロセスグループごと終了させます。
+///
+/// # 引数
+/// * `id` - ジョブ番号
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn cancel_job(id: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(mut job) = find_job(id)? else {
+        println!("{}: ジョブ #{} が見つかりません", "ERROR".red(), id);
+        return Ok(());
+    };
+
+    let previous = with_state_lock(|| {
+        // ロック中に最新の状態を読み直す
+        job = load_job(id)?;
+        let previous = job.status;
+        if !previous.is_finished() {
+            job.status = JobStatus::Cancelled;
+            job.finished_at = Some(now());
+            save_job(&job)?;
+        }
+        Ok(previous)
+    })?;
+
+    match previous {
+        JobStatus::Queued => println!("{}: ジョブ #{} をキャンセルしました", "INFO".green(), id),
+        JobStatus::Running => {
+            if let Some(pid) = job.pid {
+                // scp/rsyncなどの子プロセスも含めて終了させる
+                let _ = Command::new("kill")
+                    .args(["-TERM", "--", &format!("-{}", pid)])
+                    .stderr(Stdio::null())
+                    .status();
+            }
+            println!("{}: 実行中のジョブ #{} を停止しました", "INFO".green(), id);
+        }
+        status => println!(
+            "{}: ジョブ #{} は既に終了しています（{}）",
+            "WARN".yellow(),
+            id,
+            status.label().trim_end()
+        ),
+    }
+    Ok(())
+}
+
+/// 終了したジョブの記録と出力を削除します
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn clean_jobs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut removed = 0;
+    for job in load_jobs()? {
+        if job.status.is_finished() {
+            fs::remove_file(job_file(job.id)?)?;
+            let _ = fs::remove_file(log_file(job.id)?);
+            removed += 1;
+        }
+    }
+    println!("{}: 終了したジョブを{}件削除しました", "INFO".green(), removed);
+    Ok(())
+}
+
+/// 同時実行数を表示または設定します
+///
+/// # 引数
+/// * `limit` - 新しい同時実行数（Noneの場合は現在の値を表示）
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn set_limit(limit: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(limit) = limit else {
+        println!("同時実行数: {}", max_jobs());
+        return Ok(());
+    };
+    if limit == 0 {
+        println!("{}: 同時実行数は1以上を指定してください", "ERROR".red());
+        return Ok(());
+    }
+
+    let mut config = Config::load()?;
+    config.max_jobs = Some(limit);
+    config.save()?;
+    println!("{}: 同時実行数を {} に設定しました", "INFO".green(), limit);
+    Ok(())
+}
+
+/// キューを処理するワーカーとして動作します
+///
+/// 既に別のワーカーが動いている場合は何もせずに終了します。
+/// 待機中と実行中のジョブがなくなると終了します。
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn run_worker() -> Result<(), Box<dyn std::error::Error>> {
+    if !acquire_worker_lock()? {
+        return Ok(());
+    }
+    // ワーカーは端末から切り離されて動き、ジョブの出力はログに書き込むため色を付けない
+    colored::control::set_override(false);
+    recover_orphans()?;
+
+    let mut running: Vec<(u64, Child)> = Vec::new();
+    loop {
+        // 終了したジョブの結果を記録
+        let mut index = 0;
+        while index < running.len() {
+            match running[index].1.try_wait()? {
+                Some(status) => {
+                    let (id, _) = running.remove(index);
+                    finish_job(id, status.code())?;
+                }
+                None => index += 1,
+            }
+        }
+
+        // 空きがあれば待機中のジョブを古い順に開始
+        let queued: Vec<Job> = load_jobs()?
+            .into_iter()
+            .filter(|job| job.status == JobStatus::Queued)
+            .collect();
+        let free = max_jobs().saturating_sub(running.len());
+        for job in queued.iter().take(free) {
+            if let Some(child) = start_job(job.id)? {
+                running.push((job.id, child));
+            }
+        }
+
+        if running.is_empty() && queued.is_empty() {
+            fs::remove_file(worker_file()?)?;
+            // ロックを解放する間に登録されたジョブを取りこぼさないよう確認する
+            let pending = load_jobs()?.iter().any(|job| job.status == JobStatus::Queued);
+            if pending && acquire_worker_lock()? {
+                continue;
+            }
+            return Ok(());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// ジョブを開始します
+///
+/// 開始直前に状態を確認し、キャンセルされていれば開始しません。
+fn start_job(id: u64) -> Result<Option<Child>, Box<dyn std::error::Error>> {
+    with_state_lock(|| {
+        let mut job = load_job(id)?;
+        if job.status != JobStatus::Queued {
+            return Ok(None);
+        }
+
+        let mut log = OpenOptions::new().create(true).append(true).open(log_file(id)?)?;
+        writeln!(log, "$ sshportal {}", job.args.join(" "))?;
+        let mut command = Command::new(std::env::current_exe()?);
+        if job.confirmed {
+            command.env(safety::ALLOW_YES_ENV, "1");
+        }
+        let spawned = command
+            .args(&job.args)
+            .env_remove("CLICOLOR_FORCE")
+            .env("NO_COLOR", "1")
+            .current_dir(&job.cwd)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log.try_clone()?)
+            .process_group(0)
+            .spawn();
+
+        job.started_at = Some(now());
+        let child = match spawned {
+            Ok(child) => {
+                job.status = JobStatus::Running;
+                job.pid = Some(child.id());
+                Some(child)
+            }
+            Err(e) => {
+                writeln!(log, "{}: 起動に失敗しました: {}", "ERROR".red(), e)?;
+                job.status = JobStatus::Failed;
+                job.finished_at = Some(now());
+                None
+            }
+        };
+        save_job(&job)?;
+        Ok(child)
+    })
+}
+
+/// ジョブの終了を記録します
+///
+/// キャンセルされたジョブは状態を変更しません。
+fn finish_job(id: u64, code: Option<i32>) -> Result<(), Box<dyn std::error::Error>> {
+    with_state_lock(|| {
+        let mut job = load_job(id)?;
+        job.pid = None;
+        job.exit_code = code;
+        if job.status != JobStatus::Cancelled {
+            job.status = if code == Some(0) { JobStatus::Succeeded } else { JobStatus::Failed };
+            job.finished_at = Some(now());
+        }
+        save_job(&job)
+    })
+}
+
+/// 前回のワーカーが異常終了した場合に、実行中のまま残ったジョブを失敗として記録します
+fn recover_orphans() -> Result<(), Box<dyn std::error::Error>> {
+    for job in load_jobs()? {
+        if job.status == JobStatus::Running && !job.pid.is_some_and(is_alive) {
+            finish_job(job.id, None)?;
+        }
+    }
+    Ok(())
+}
+
+/// ワーカーが動いていなければ、端末から切り離して起動します
+fn start_worker() -> Result<(), Box<dyn std::error::Error>> {
+    if read_pid(&worker_file()?).is_some_and(is_alive) {
+        return Ok(());
+    }
+    Command::new(std::env::current_exe()?)
+        .args(["jobs", "worker"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    Ok(())
+}
+
+/// ワーカーのロックを取得します
+///
+/// # 戻り値
+/// 取得できた場合はtrue、別のワーカーが動いている場合はfalse
+fn acquire_worker_lock() -> Result<bool, Box<dyn std::error::Error>> {
+    let path = worker_file()?;
+    fs::create_dir_all(jobs_dir()?)?;
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                write!(file, "{}", std::process::id())?;
+                return Ok(true);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if read_pid(&path).is_some_and(is_alive) {
+                    return Ok(false);
+                }
+                // 異常終了したワーカーのロックを削除して再試行する
+                let _ = fs::remove_file(&path);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(false)
+}
+
+/// ジョブの状態ファイルを排他的に更新するためのロックを取得して処理を実行します
+fn with_state_lock<T>(
+    f: impl FnOnce() -> Result<T, Box<dyn std::error::Error>>,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let path = jobs_dir()?.join("state.lock");
+    let mut waited = Duration::ZERO;
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && waited < LOCK_TIMEOUT => {
+                thread::sleep(Duration::from_millis(50));
+                waited += Duration::from_millis(50);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let _ = fs::remove_file(&path);
+                waited = Duration::ZERO;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let result = f();
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// 全てのジョブを番号順に読み込みます
+fn load_jobs() -> Result<Vec<Job>, Box<dyn std::error::Error>> {
+    let dir = jobs_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut jobs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            // 登録直後で内容がまだ書き込まれていないファイルは読み飛ばす
+            if let Ok(job) = serde_json::from_str::<Job>(&fs::read_to_string(&path)?) {
+                jobs.push(job);
+            }
+        }
+    }
+    jobs.sort_by_key(|job| job.id);
+    Ok(jobs)
+}
+
+/// 番号を指定してジョブを探します
+fn find_job(id: u64) -> Result<Option<Job>, Box<dyn std::error::Error>> {
+    if !job_file(id)?.exists() {
+        return Ok(None);
+    }
+    Ok(Some(load_job(id)?))
+}
+
+/// ジョブを読み込みます
+fn load_job(id: u64) -> Result<Job, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_str(&fs::read_to_string(job_file(id)?)?)?)
+}
+
+/// ジョブを保存します
+///
+/// 読み込み中のプロセスが書きかけの内容を読まないよう、一時ファイルに書いてから置き換えます。
+fn save_job(job: &Job) -> Result<(), Box<dyn std::error::Error>> {
+    let path = job_file(job.id)?;
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, serde_json::to_string_pretty(job)?)?;
+    fs::rename(temp, path)?;
+    Ok(())
+}
+
+/// 設定されている同時実行数を返します
+fn max_jobs() -> usize {
+    Config::load()
+        .ok()
+        .and_then(|config| config.max_jobs)
+        .unwrap_or(DEFAULT_MAX_JOBS)
+        .max(1)
+}
+
+/// プロセスが存在するかどうかを確認します
+fn is_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// PIDファイルからプロセスIDを読み込みます
+fn read_pid(path: &PathBuf) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// ジョブを保存するディレクトリ（~/.config/sshportal/jobs/）
+fn jobs_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(Config::config_dir()?.join("jobs"))
+}
+
+/// ジョブの状態ファイルのパス
+fn job_file(id: u64) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(jobs_dir()?.join(format!("{}.json", id)))
+}
+
+/// ジョブの出力ファイルのパス
+fn log_file(id: u64) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(jobs_dir()?.join(format!("{}.log", id)))
+}
+
+/// ワーカーのPIDファイルのパス
+fn worker_file() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(jobs_dir()?.join("worker.pid"))
+}
+
+/// 現在の日時を表示用の形式で返します
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn job_args_removes_only_the_background_flag() {
+        assert_eq!(
+            job_args(args(&["copy", "--background", "a", "prod:b"]), false),
+            args(&["copy", "a", "prod:b"])
+        );
+        assert_eq!(
+            job_args(args(&["copy", "--background", "--", "--background", "prod:b"]), false),
+            args(&["copy", "--", "--background", "prod:b"])
+        );
+    }
+
+    #[test]
+    fn job_args_adds_yes_before_positional_separator() {
+        assert_eq!(
+            job_args(args(&["push", "--background", "--", "-y", "prod:"]), true),
+            args(&["push", "--yes", "--", "-y", "prod:"])
+        );
+        assert_eq!(job_args(args(&["push", "-y", "a", "prod:"]), true), args(&["push", "-y", "a", "prod:"]));
+    }
+}
@@ -0,0 +1,305 @@
+// SSH鍵の生成と配布機能
+//
+// このモジュールは、ホスト専用のed25519鍵をssh-keygenで生成し、現在の認証方法で
+// 公開鍵をリモートの authorized_keys に登録します。鍵の更新時は新しい鍵で
+// 接続できることを確認してから、古い鍵を authorized_keys から削除します。
+
+use crate::argv;
+use crate::config::Config;
+use crate::remote;
+use crate::resolve::{self, Endpoint};
+use crate::safety;
+use colored::*;
+use std::fs;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::Path;
+use std::process::Command;
+
+/// 公開鍵を authorized_keys に追加するリモートスクリプト
+///
+/// 標準入力から公開鍵を1行読み込み、登録済みでなければ追記します。
+/// 末尾に改行のないファイルでも前の行と連結されないよう改行を補います。
+const INSTALL_SCRIPT: &str = r#"umask 077; read -r key || exit 1; f="$HOME/.ssh/authorized_keys"; mkdir -p "$HOME/.ssh" && touch "$f" || exit 1; grep -qxF -- "$key" "$f" && exit 0; if [ -s "$f" ] && [ -n "$(tail -c 1 "$f")" ]; then echo >> "$f"; fi; printf '%s\n' "$key" >> "$f""#;
+
+/// 公開鍵を authorized_keys から削除するリモートスクリプト
+///
+/// 標準入力から鍵の本体（base64部分）を1行読み込み、それを含む行を削除します。
+/// ファイルの所有者と権限を保つため、一時ファイルの内容を書き戻します。
+const REVOKE_SCRIPT: &str = r#"read -r blob || exit 1; f="$HOME/.ssh/authorized_keys"; [ -f "$f" ] || exit 0; grep -vF -- "$blob" "$f" > "$f.sshportal"; [ $? -le 1 ] && cat "$f.sshportal" > "$f" && rm -f -- "$f.sshportal""#;
+
+/// ホスト専用の鍵を生成して登録します
+///
+/// `~/.ssh/sshportal_<ホスト名>_ed25519` に鍵を生成し、現在の認証方法（設定済みの鍵、
+/// ssh-agent、パスワードなど）で公開鍵をホストの authorized_keys に追加します。
+/// 新しい鍵で接続できることを確認してから、ホストの `key_path` に設定します。
+///
+/// # 引数
+/// * `name` - ホストのエイリアス名
+/// * `yes` - 保護されたホストの確認を省略するかどうか（`--yes`）
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn new_key(name: &str, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+    if !config.hosts.contains_key(name) {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    }
+    let endpoint = resolve::resolve_host(&config, name)?;
+    safety::check_write(&config, &endpoint, yes)?;
+
+    let key_path = key_file(name);
+    let key = Config::expand_path(&key_path)?;
+    if Path::new(&key).exists() {
+        return Err(format!(
+            "鍵 {} は既に存在します。鍵を更新するには 'sshportal key rotate {}' を使用してください",
+            key, name
+        )
+        .into());
+    }
+
+    generate(&key, name)?;
+    println!("{}: 鍵 {} を生成しました", "INFO".green(), key);
+
+    let public = public_key(&key)?;
+    println!("{}: 公開鍵を '{}' に登録しています...", "INFO".green(), endpoint.connection);
+    if let Err(e) = remote::run_with_input(&endpoint, INSTALL_SCRIPT, format!("{}\n", public).as_bytes()) {
+        // 登録できなかった鍵は使われないため削除して、再実行できるようにする
+        remove_key(&key);
+        return Err(format!("公開鍵を登録できませんでした: {}", e).into());
+    }
+    if let Err(e) = check_login(&endpoint, &key) {
+        discard_key(&endpoint, &key, &public);
+        return Err(e);
+    }
+
+    if let Some(host) = config.hosts.get_mut(name) {
+        host.key_path = Some(key_path.clone());
+    }
+    config.save()?;
+    println!(
+        "{}: ホスト '{}' の鍵を {} に設定しました",
+        "SUCCESS".green(),
+        name,
+        key_path
+    );
+    Ok(())
+}
+
+/// ホストの鍵を新しい鍵に更新し、古い鍵を無効化します
+///
+/// 新しい鍵を生成して現在の鍵で登録し、新しい鍵で接続できることを確認してから、
+/// 新しい鍵で接続して古い公開鍵を authorized_keys から削除します。
+/// 古い鍵が `key new` で生成したホスト専用の鍵の場合は `<鍵>.<日時>` に退避します。
+/// それ以外の鍵は他のホストで使われている可能性があるため、ローカルには残します。
+///
+/// # 引数
+/// * `name` - ホストのエイリアス名
+/// * `yes` - 保護されたホストの確認を省略するかどうか（`--yes`）
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn rotate_key(name: &str, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+    let Some(host) = config.hosts.get(name) else {
+        println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    };
+    let Some(ref old_path) = host.key_path else {
+        return Err(format!(
+            "ホスト '{}' には鍵が設定されていません。'sshportal key new {}' で作成してください",
+            name, name
+        )
+        .into());
+    };
+    let old = Config::expand_path(old_path)?;
+    let endpoint = resolve::resolve_host(&config, name)?;
+    safety::check_write(&config, &endpoint, yes)?;
+
+    // 接続に失敗した場合に備え、古い公開鍵は変更前に取得しておく
+    let old_blob = key_blob(&public_key(&old)?)?;
+
+    let key_path = key_file(name);
+    let key = Config::expand_path(&key_path)?;
+    let dedicated = Path::new(&key) == Path::new(&old);
+    if !dedicated && Path::new(&key).exists() {
+        return Err(format!("鍵 {} は既に存在します", key).into());
+    }
+    // ホスト専用の鍵を更新する場合は、確認が終わるまで別名で生成する
+    let staging = if dedicated { format!("{}.new", key) } else { key.clone() };
+    remove_key(&staging);
+
+    generate(&staging, name)?;
+    println!("{}: 新しい鍵を生成しました", "INFO".green());
+
+    let public = public_key(&staging)?;
+    println!("{}: 新しい公開鍵を '{}' に登録しています...", "INFO".green(), endpoint.connection);
+    if let Err(e) = remote::run_with_input(&endpoint, INSTALL_SCRIPT, format!("{}\n", public).as_bytes()) {
+        remove_key(&staging);
+        return Err(format!("新しい公開鍵を登録できませんでした: {}", e).into());
+    }
+    if let Err(e) = check_login(&endpoint, &staging) {
+        discard_key(&endpoint, &staging, &public);
+        return Err(e);
+    }
+
+    // 新しい鍵で接続して古い鍵を削除する（途中で失敗しても締め出されないように）
+    let new_endpoint = Endpoint { key_path: Some(staging.clone()), ..endpoint.clone() };
+    let revoked = remote::run_with_input(&new_endpoint, REVOKE_SCRIPT, format!("{}\n", old_blob).as_bytes());
+
+    if dedicated {
+        let suffix = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        for ext in ["", ".pub"] {
+            let from = format!("{}{}", key, ext);
+            if Path::new(&from).exists() {
+                fs::rename(&from, format!("{}.{}", from, suffix))?;
+            }
+            fs::rename(format!("{}{}", staging, ext), &from)?;
+        }
+        println!("{}: 古い鍵を {}.{} に退避しました", "INFO".green(), key, suffix);
+    }
+
+    if let Some(host) = config.hosts.get_mut(name) {
+        host.key_path = Some(key_path.clone());
+    }
+    config.save()?;
+
+    match revoked {
+        Ok(()) => println!(
+            "{}: ホスト '{}' の鍵を {} に更新し、古い鍵を無効化しました",
+            "SUCCESS".green(),
+            name,
+            key_path
+        ),
+        Err(e) => println!(
+            "{}: 鍵を {} に更新しましたが、古い鍵を authorized_keys から削除できませんでした: {}",
+            "WARN".yellow(),
+            key_path,
+            e
+        ),
+    }
+
+    // 同じ接続先で古い鍵を使っている他のホストは、この更新で接続できなくなる
+    for (other, host) in &config.hosts {
+        if other != name
+            && host.connection == endpoint.connection
+            && host.port == endpoint.port
+            && host.key_path.as_ref().and_then(|k| Config::expand_path(k).ok()).as_deref() == Some(old.as_str())
+        {
+            println!(
+                "{}: ホスト '{}' は無効化した古い鍵を使用しています。'sshportal key new {}' で鍵を作成してください",
+                "WARN".yellow(),
+                other,
+                other
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 接続を確認できなかった鍵の登録を取り消し、ローカルの鍵を削除します
+///
+/// 使われない公開鍵が authorized_keys に残らないよう、登録に使用した接続先で削除します。
+///
+/// # 引数
+/// * `endpoint` - 公開鍵の登録に使用した接続先
+/// * `key` - 秘密鍵のパス
+/// * `public` - 登録した公開鍵
+fn discard_key(endpoint: &Endpoint, key: &str, public: &str) {
+    let revoked = key_blob(public).and_then(|blob| {
+        remote::run_with_input(endpoint, REVOKE_SCRIPT, format!("{}\n", blob).as_bytes()).map_err(Into::into)
+    });
+    if let Err(e) = revoked {
+        println!(
+            "{}: 登録した公開鍵を '{}' の authorized_keys から削除できませんでした: {}",
+            "WARN".yellow(),
+            endpoint.connection,
+            e
+        );
+    }
+    remove_key(key);
+}
+
+/// ホスト専用の鍵のパス（`~` を含む設定用の表記）を返します
+fn key_file(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect();
+    format!("~/.ssh/sshportal_{}_ed25519", safe)
+}
+
+/// ssh-keygenでパスフレーズなしのed25519鍵を生成します
+fn generate(key: &str, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !argv::is_available("ssh-keygen") {
+        return Err("ssh-keygen がPATHにありません".into());
+    }
+    if let Some(dir) = Path::new(key).parent() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    let comment = format!("sshportal-{}-{}", name, chrono::Local::now().format("%Y%m%d"));
+    let status = Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-C", &comment, "-f", key])
+        .status()?;
+    if !status.success() {
+        return Err(format!("ssh-keygen が失敗しました（終了コード: {:?}）", status.code()).into());
+    }
+    Ok(())
+}
+
+/// 秘密鍵に対応する公開鍵（1行）を返します
+///
+/// `<鍵>.pub` があればその内容を、なければ `ssh-keygen -y` で秘密鍵から求めます。
+fn public_key(key: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if let Ok(content) = fs::read_to_string(format!("{}.pub", key)) {
+        if let Some(line) = content.lines().find(|line| !line.trim().is_empty()) {
+            return Ok(line.trim().to_string());
+        }
+    }
+    let output = Command::new("ssh-keygen").args(["-y", "-f", key]).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "鍵 {} の公開鍵を取得できませんでした: {}",
+            key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// 公開鍵の本体（base64部分）を返します
+fn key_blob(public: &str) -> Result<String, Box<dyn std::error::Error>> {
+    public
+        .split_whitespace()
+        .nth(1)
+        .map(str::to_string)
+        .ok_or_else(|| format!("公開鍵の形式が正しくありません: {}", public).into())
+}
+
+/// 指定した鍵だけで接続できることを確認します
+fn check_login(endpoint: &Endpoint, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let endpoint = Endpoint { key_path: Some(key.to_string()), ..endpoint.clone() };
+    let mut argv = argv::ssh_exec_argv(&endpoint, "true");
+    // ssh-agentの鍵やパスワードで接続できてしまわないよう、この鍵のみで認証する
+    for option in ["IdentitiesOnly=yes", "PasswordAuthentication=no", "KbdInteractiveAuthentication=no"] {
+        argv.insert(1, option.to_string());
+        argv.insert(1, "-o".to_string());
+    }
+    let status = argv::command(&argv).status()?;
+    if !status.success() {
+        return Err(format!(
+            "新しい鍵 {} で接続できませんでした（登録した公開鍵と鍵は削除し、設定は変更していません）",
+            key
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// 秘密鍵と公開鍵のファイルを削除します（存在しない場合は何もしません）
+fn remove_key(key: &str) {
+    for ext in ["", ".pub"] {
+        let _ = fs::remove_file(format!("{}{}", key, ext));
+    }
+}
@@ -0,0 +1,50 @@
+// sshportal - SSH接続とSCP転送を簡略化するライブラリ
+//
+// このクレートは設定ファイルの読み書き、ホストとパスエイリアスの解決、
+// およびssh/scp/rsyncの引数構築を公開APIとして提供します。
+// コマンドラインツール（sshportalバイナリ）はこのライブラリの薄いラッパーです。
+
+pub mod argv;     // ssh/scp/rsyncの引数構築機能
+pub mod config;   // 設定ファイルの読み書き機能
+pub mod error;    // ライブラリのエラー型
+#[cfg(feature = "native")]
+pub mod native;   // ネイティブSSH/SFTP転送機能
+pub mod resolve;  // ホストとパスエイリアスの解決機能
+
+mod audit;        // 監査ログ機能
+mod browser;      // リモートディレクトリブラウザ
+mod checksum;     // チェックサム計算機能
+mod clobber;      // 上書きの検出機能
+mod commands;     // コマンドライン引数の定義と処理
+mod compare;      // 差分表示コマンド
+mod diff;         // 差分表示機能
+mod doctor;       // 設定の診断機能
+mod edit;         // リモートファイル編集機能
+mod filter;       // 除外パターン機能
+mod host;         // ホスト管理機能
+mod hostkey;      // ホスト鍵の記録と検証機能
+mod jobs;         // バックグラウンドジョブ機能
+mod key;          // SSH鍵の生成と配布機能
+mod path;         // パス管理機能
+mod progress;     // 転送の進捗表示機能
+mod remote;       // ssh経由のリモート操作機能
+mod safety;       // ホストの安全レベル機能
+mod tar_transfer; // tarストリーム転送機能
+mod template;     // パステンプレート機能
+mod transfer;     // ファイル転送機能
+mod verify;       // 転送後の検証機能
+mod watch;        // 監視同期機能
+
+pub use config::{Config, Host};
+pub use error::{Error, Result};
+pub use resolve::{resolve_host, resolve_spec, AliasSource, Endpoint, Location};
+pub use progress::ProgressEvent;
+
+/// コマンドライン引数を解析してサブコマンドを実行します
+///
+/// sshportalバイナリのエントリポイントです。ライブラリの公開APIではありません。
+#[doc(hidden)]
+pub fn run() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use clap::Parser;
+    commands::handle_command(commands::Cli::parse())
+}
@@ -2,14 +2,8 @@
 //
 // このプログラムはSSH接続先とパスのエイリアス管理を提供し、
 // zshでの効率的なSSH作業をサポートします。
+// 機能の実体はsshportalライブラリ（lib.rs）にあります。
 
-mod config;   // 設定ファイルの読み書き機能
-mod host;     // ホスト管理機能
-mod path;     // パス管理とファイル転送機能
-mod commands; // コマンドライン引数の定義と処理
-
-use clap::Parser;
-use commands::{Cli, handle_command};
 use colored::*;
 
 /// メイン関数
@@ -18,13 +12,10 @@ use colored::*;
 /// エラーが発生した場合は、色付きでエラーメッセージを表示し、
 /// 終了コード1でプログラムを終了します。
 fn main() {
-    // コマンドライン引数を解析
-    let cli = Cli::parse();
-    
-    // コマンドを実行し、エラーが発生した場合は適切に処理
-    if let Err(e) = handle_command(cli) {
+    // コマンドライン引数を解析して実行し、エラーが発生した場合は適切に処理
+    if let Err(e) = sshportal::run() {
         // 赤色でエラーメッセージを表示
         eprintln!("{}: {}", "Error".red(), e);
         std::process::exit(1);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,580 @@
+// ネイティブSSH/SFTP転送機能（`native`フィーチャー）
+//
+// このモジュールは、外部の`scp`コマンドやCライブラリに依存せず、純粋なRustの
+// SSH実装（russh・russh-sftp）を使用してSFTPでファイルをアップロード/ダウンロードします。
+// 途中から再開（レジューム）とSHA-256によるチェックサム検証に対応します。
+
+use crate::checksum::sha256_reader;
+use crate::error::{Error, Result};
+use crate::progress::ProgressEvent;
+use crate::resolve::{Endpoint, Location};
+use russh::client::{self, Handle};
+use russh::keys::agent::client::AgentClient;
+use russh::keys::{self, HashAlg, PrivateKeyWithHashAlg, PublicKey};
+use russh::{cipher, compression, ChannelMsg, Preferred};
+use russh_sftp::client::SftpSession as Sftp;
+use russh_sftp::protocol::OpenFlags;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::runtime::Runtime;
+
+/// 転送時のバッファサイズ
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// SSHの圧縮を使用する場合の圧縮方式の優先順
+const COMPRESSED: &[compression::Name] = &[compression::ZLIB_LEGACY, compression::ZLIB, compression::NONE];
+
+/// SSHの圧縮を使用しない場合の圧縮方式
+const UNCOMPRESSED: &[compression::Name] = &[compression::NONE];
+
+/// ネイティブ転送のオプション
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct TransferOptions {
+    /// 転送先に途中までのファイルがある場合、続きから転送する
+    pub resume: bool,
+    /// 転送後にSHA-256で内容を検証する（再開したファイルは常に検証する）
+    pub verify: bool,
+}
+
+/// SFTPセッション
+///
+/// 接続済み・認証済みのSSHセッションとSFTPサブシステムを保持します。
+/// 非同期のSSH実装を同期的に扱うため、セッション専用のランタイムを持ちます。
+pub struct SftpSession {
+    session: Handle<HostKeyCheck>,
+    sftp: Sftp,
+    runtime: Runtime,
+}
+
+impl SftpSession {
+    /// 接続先にSSH接続し、SFTPセッションを開始します
+    ///
+    /// ホスト鍵は`~/.ssh/known_hosts`（設定の`known_hosts`があればそのファイル）で検証します。認証には接続先の
+    /// `key_path`を優先し、指定がない場合や失敗した場合はssh-agentを使用します。
+    ///
+    /// # 引数
+    /// * `endpoint` - 接続先
+    ///
+    /// # 戻り値
+    /// SFTPセッション、またはエラー
+    pub fn connect(endpoint: &Endpoint) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let (session, sftp) = runtime.block_on(connect(endpoint))?;
+        Ok(SftpSession { session, sftp, runtime })
+    }
+
+    /// ローカルのファイルまたはディレクトリをアップロードします
+    ///
+    /// `scp -r`と同様に、転送先が既存のディレクトリの場合は
+    /// その中にコピー元と同じ名前で作成します。
+    ///
+    /// # 引数
+    /// * `local` - コピー元のローカルパス
+    /// * `remote` - コピー先のリモートパス
+    /// * `options` - 転送オプション
+    /// * `progress` - 進捗イベントを受け取るコールバック
+    pub fn upload(
+        &self,
+        local: &Path,
+        remote: &str,
+        options: &TransferOptions,
+        progress: &mut dyn FnMut(ProgressEvent),
+    ) -> Result<()> {
+        self.runtime.block_on(async {
+            let mut target = sftp_path(remote);
+            if self.is_remote_dir(&target).await {
+                if let Some(name) = local.file_name() {
+                    target = join(&target, &name.to_string_lossy());
+                }
+            }
+            self.upload_entry(local, &target, options, progress).await
+        })
+    }
+
+    /// リモートのファイルまたはディレクトリをダウンロードします
+    ///
+    /// 転送先が既存のディレクトリの場合は、その中にコピー元と同じ名前で作成します。
+    ///
+    /// # 引数
+    /// * `remote` - コピー元のリモートパス
+    /// * `local` - コピー先のローカルパス
+    /// * `options` - 転送オプション
+    /// * `progress` - 進捗イベントを受け取るコールバック
+    pub fn download(
+        &self,
+        remote: &str,
+        local: &Path,
+        options: &TransferOptions,
+        progress: &mut dyn FnMut(ProgressEvent),
+    ) -> Result<()> {
+        self.runtime.block_on(async {
+            let source = sftp_path(remote);
+            let mut target = local.to_path_buf();
+            if target.is_dir() {
+                if let Some(name) = Path::new(&source).file_name() {
+                    target = target.join(name);
+                }
+            }
+            self.download_entry(&source, &target, options, progress).await
+        })
+    }
+
+    /// リモートパスのSHA-256を計算します
+    ///
+    /// リモートの`sha256sum`または`shasum -a 256`を優先し、
+    /// どちらも利用できない場合はSFTPで内容を読み出して計算します。
+    pub fn remote_sha256(&self, remote: &str) -> Result<String> {
+        self.runtime.block_on(self.sha256_of(&sftp_path(remote)))
+    }
+
+    /// リモートでコマンドを実行し、成功した場合は標準出力を返します
+    ///
+    /// # 戻り値
+    /// 終了コード0の場合はSome(標準出力)、それ以外（実行を拒否された場合を含む）はNone
+    pub fn exec(&self, command: &str) -> Result<Option<String>> {
+        self.runtime.block_on(self.exec_async(command))
+    }
+
+    /// `exec`の非同期版
+    async fn exec_async(&self, command: &str) -> Result<Option<String>> {
+        let mut channel = self.session.channel_open_session().await?;
+        channel.exec(true, command).await?;
+
+        let mut output = Vec::new();
+        let mut status = None;
+        while let Some(message) = channel.wait().await {
+            match message {
+                ChannelMsg::Data { ref data } => output.extend_from_slice(data),
+                ChannelMsg::ExitStatus { exit_status } => status = Some(exit_status),
+                // サーバーがコマンドの実行を拒否した（SFTP専用のサーバーなど）
+                ChannelMsg::Failure => {
+                    let _ = channel.close().await;
+                    return Ok(None);
+                }
+                _ => {}
+            }
+        }
+        match status {
+            Some(0) => Ok(Some(String::from_utf8_lossy(&output).to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// `remote_sha256`の非同期版
+    async fn sha256_of(&self, remote: &str) -> Result<String> {
+        let quoted = crate::argv::shell_quote(remote);
+        for tool in ["sha256sum", "shasum -a 256"] {
+            if let Some(output) = self.exec_async(&format!("{} {}", tool, quoted)).await? {
+                if let Some(hash) = output.split_whitespace().next() {
+                    return Ok(hash.to_string());
+                }
+            }
+        }
+
+        let mut file = self.sftp.open(remote).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// リモートパスがディレクトリかどうかを返します
+    async fn is_remote_dir(&self, path: &str) -> bool {
+        self.sftp.metadata(path).await.is_ok_and(|stat| stat.is_dir())
+    }
+
+    /// 1エントリ（ファイルまたはディレクトリ）を再帰的にアップロードします
+    async fn upload_entry(
+        &self,
+        local: &Path,
+        remote: &str,
+        options: &TransferOptions,
+        progress: &mut dyn FnMut(ProgressEvent),
+    ) -> Result<()> {
+        let mut pending = vec![(local.to_path_buf(), remote.to_string())];
+        while let Some((local, remote)) = pending.pop() {
+            if local.is_dir() {
+                if !self.is_remote_dir(&remote).await {
+                    self.sftp.create_dir(remote.as_str()).await?;
+                }
+                for entry in fs::read_dir(&local)? {
+                    let entry = entry?;
+                    pending.push((entry.path(), join(&remote, &entry.file_name().to_string_lossy())));
+                }
+                continue;
+            }
+            self.upload_file(&local, &remote, options, progress).await?;
+        }
+        Ok(())
+    }
+
+    /// 1ファイルをアップロードします
+    async fn upload_file(
+        &self,
+        local: &Path,
+        remote: &str,
+        options: &TransferOptions,
+        progress: &mut dyn FnMut(ProgressEvent),
+    ) -> Result<()> {
+        let mut src = fs::File::open(local)?;
+        let total = src.metadata()?.len();
+        progress(ProgressEvent::FileStarted { path: local, size: total });
+
+        // 途中までのファイルがある場合は続きから転送
+        let offset = match self.sftp.metadata(remote).await {
+            Ok(stat) if options.resume => stat.size.filter(|&size| size <= total).unwrap_or(0),
+            _ => 0,
+        };
+        let flags = if offset > 0 {
+            OpenFlags::WRITE
+        } else {
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+        };
+        let mut dst = self.sftp.open_with_flags(remote, flags).await?;
+        if offset > 0 {
+            src.seek(SeekFrom::Start(offset))?;
+            dst.seek(SeekFrom::Start(offset)).await?;
+            progress(ProgressEvent::Resumed(offset));
+        }
+
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            let n = src.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            dst.write_all(&buffer[..n]).await?;
+            progress(ProgressEvent::Transferred(n as u64));
+        }
+        dst.shutdown().await?;
+
+        // 再開したファイルは途中までの内容が正しいとは限らないため、常に検証する
+        if options.verify || offset > 0 {
+            let expected = sha256_reader(fs::File::open(local)?)?;
+            let actual = self.sha256_of(remote).await?;
+            if expected != actual {
+                return Err(Error::ChecksumMismatch(vec![remote.to_string()]));
+            }
+        }
+        progress(ProgressEvent::FileFinished);
+        Ok(())
+    }
+
+    /// 1エントリ（ファイルまたはディレクトリ）を再帰的にダウンロードします
+    async fn download_entry(
+        &self,
+        remote: &str,
+        local: &Path,
+        options: &TransferOptions,
+        progress: &mut dyn FnMut(ProgressEvent),
+    ) -> Result<()> {
+        let mut pending = vec![(remote.to_string(), local.to_path_buf())];
+        while let Some((remote, local)) = pending.pop() {
+            let stat = self.sftp.metadata(remote.as_str()).await?;
+            if stat.is_dir() {
+                fs::create_dir_all(&local)?;
+                for entry in self.sftp.read_dir(remote.as_str()).await? {
+                    let name = entry.file_name();
+                    if name != "." && name != ".." {
+                        pending.push((join(&remote, &name), local.join(&name)));
+                    }
+                }
+                continue;
+            }
+            self.download_file(&remote, &local, stat.size.unwrap_or(0), options, progress)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 1ファイルをダウンロードします
+    async fn download_file(
+        &self,
+        remote: &str,
+        local: &Path,
+        total: u64,
+        options: &TransferOptions,
+        progress: &mut dyn FnMut(ProgressEvent),
+    ) -> Result<()> {
+        progress(ProgressEvent::FileStarted { path: Path::new(remote), size: total });
+
+        // 途中までのファイルがある場合は続きから転送
+        let offset = match fs::metadata(local) {
+            Ok(meta) if options.resume && meta.len() <= total => meta.len(),
+            _ => 0,
+        };
+        let mut src = self.sftp.open(remote).await?;
+        let mut dst = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(offset == 0)
+            .open(local)?;
+        if offset > 0 {
+            src.seek(SeekFrom::Start(offset)).await?;
+            dst.seek(SeekFrom::Start(offset))?;
+            progress(ProgressEvent::Resumed(offset));
+        }
+
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            let n = src.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            dst.write_all(&buffer[..n])?;
+            progress(ProgressEvent::Transferred(n as u64));
+        }
+        dst.flush()?;
+
+        // 再開したファイルは途中までの内容が正しいとは限らないため、常に検証する
+        if options.verify || offset > 0 {
+            let expected = self.sha256_of(remote).await?;
+            let actual = sha256_reader(fs::File::open(local)?)?;
+            if expected != actual {
+                return Err(Error::ChecksumMismatch(vec![local.to_string_lossy().to_string()]));
+            }
+        }
+        progress(ProgressEvent::FileFinished);
+        Ok(())
+    }
+}
+
+/// ネイティブ転送でファイルをコピーします
+///
+/// ローカル→リモート、リモート→ローカルのコピーに対応します。
+/// コピー元が複数の場合、全てのコピー元は同じ側（ローカルまたは同一ホスト）である必要があります。
+/// SFTPはglobを展開しないため、リモートのglobはエラーになります。
+///
+/// # 引数
+/// * `sources` - コピー元（1つ以上）
+/// * `dst` - コピー先
+/// * `options` - 転送オプション
+/// * `progress` - 進捗イベントを受け取るコールバック
+pub fn copy(
+    sources: &[Location],
+    dst: &Location,
+    options: &TransferOptions,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> Result<()> {
+    let unsupported = || {
+        Error::Unsupported("ネイティブ転送はローカルとリモート間のコピーのみ対応しています".to_string())
+    };
+
+    match dst {
+        Location::Remote { endpoint, path } => {
+            let session = SftpSession::connect(endpoint)?;
+            for src in sources {
+                let Location::Local(local) = src else { return Err(unsupported()) };
+                session.upload(Path::new(local), path, options, progress)?;
+            }
+        }
+        Location::Local(local) => {
+            let endpoint = sources.first().and_then(Location::endpoint).ok_or_else(unsupported)?;
+            // 接続の前に、対応していないコピー元がないかを確認する
+            for src in sources {
+                match src {
+                    Location::Remote { endpoint: e, path } if e == endpoint => {
+                        if crate::resolve::has_glob(path) {
+                            return Err(Error::Unsupported(format!(
+                                "ネイティブ転送はリモートのglob '{}' に対応していません",
+                                path
+                            )));
+                        }
+                    }
+                    _ => return Err(unsupported()),
+                }
+            }
+            let session = SftpSession::connect(endpoint)?;
+            for src in sources {
+                session.download(src.path(), Path::new(local), options, progress)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// SSH接続でのホスト鍵の検証
+///
+/// russhのクライアントハンドラとして、サーバーのホスト鍵をknown_hostsファイルと照合します。
+struct HostKeyCheck {
+    /// 接続先のホスト名
+    host: String,
+    /// 接続先のポート番号
+    port: u16,
+    /// 照合するknown_hostsファイル
+    known_hosts: PathBuf,
+}
+
+impl client::Handler for HostKeyCheck {
+    type Error = Error;
+
+    async fn check_server_key(&mut self, key: &PublicKey) -> Result<bool> {
+        let not_found = || {
+            Error::Transport(format!(
+                "ホスト '{}' はknown_hostsに登録されていません。先に 'sshportal connect' で接続してください",
+                self.host
+            ))
+        };
+        if !self.known_hosts.exists() {
+            return Err(not_found());
+        }
+        match keys::check_known_hosts_path(&self.host, self.port, key, &self.known_hosts) {
+            Ok(true) => Ok(true),
+            Ok(false) => Err(not_found()),
+            Err(keys::Error::KeyChanged { .. }) => Err(Error::Transport(format!(
+                "ホスト '{}' のホスト鍵がknown_hostsと一致しません。'sshportal hostkey verify' で確認してください",
+                self.host
+            ))),
+            Err(e) => Err(Error::Transport(format!("ホスト鍵の検証に失敗しました: {}", e))),
+        }
+    }
+}
+
+/// SSH接続と認証を行い、SFTPサブシステムを開始します
+async fn connect(endpoint: &Endpoint) -> Result<(Handle<HostKeyCheck>, Sftp)> {
+    let (user, host) = split_connection(&endpoint.connection)?;
+
+    let mut preferred = Preferred {
+        compression: Cow::Borrowed(if endpoint.compression { COMPRESSED } else { UNCOMPRESSED }),
+        ..Preferred::default()
+    };
+    if let Some(ref ciphers) = endpoint.cipher {
+        preferred.cipher = Cow::Owned(parse_ciphers(ciphers)?);
+    }
+    let config = client::Config {
+        preferred,
+        ..client::Config::default()
+    };
+
+    let known_hosts = match endpoint.known_hosts {
+        Some(ref file) => PathBuf::from(file),
+        None => dirs::home_dir()
+            .ok_or(Error::HomeDirNotFound)?
+            .join(".ssh")
+            .join("known_hosts"),
+    };
+    let handler = HostKeyCheck {
+        host: host.clone(),
+        port: endpoint.port,
+        known_hosts,
+    };
+    let mut session = client::connect(Arc::new(config), (host.as_str(), endpoint.port), handler).await?;
+
+    // 鍵ファイルでの認証に失敗した場合はssh-agentにフォールバック
+    let mut authenticated = false;
+    if let Some(ref key_path) = endpoint.key_path {
+        if let Ok(key) = keys::load_secret_key(key_path, None) {
+            let hash = rsa_hash(&session, key.public_key()).await?;
+            authenticated = session
+                .authenticate_publickey(user.as_str(), PrivateKeyWithHashAlg::new(Arc::new(key), hash))
+                .await?
+                .success();
+        }
+    }
+    if !authenticated {
+        authenticated = authenticate_agent(&mut session, &user).await?;
+    }
+    if !authenticated {
+        return Err(Error::Transport(format!("'{}' の認証に失敗しました", endpoint.connection)));
+    }
+
+    let channel = session.channel_open_session().await?;
+    channel.request_subsystem(true, "sftp").await?;
+    let sftp = Sftp::new(channel.into_stream()).await?;
+    Ok((session, sftp))
+}
+
+/// ssh-agentの鍵で順に認証を試みます
+///
+/// # 戻り値
+/// 認証に成功した場合はtrue（ssh-agentが利用できない場合はfalse）
+async fn authenticate_agent(session: &mut Handle<HostKeyCheck>, user: &str) -> Result<bool> {
+    let Ok(mut agent) = AgentClient::connect_env().await else {
+        return Ok(false);
+    };
+    let identities = agent.request_identities().await.unwrap_or_default();
+    for key in identities {
+        let hash = rsa_hash(session, &key).await?;
+        let result = session
+            .authenticate_publickey_with(user, key, hash, &mut agent)
+            .await
+            .map_err(|e| Error::Transport(format!("ssh-agentでの認証に失敗しました: {}", e)))?;
+        if result.success() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// RSA鍵の場合に、サーバーが対応する署名のハッシュ方式を返します
+async fn rsa_hash(session: &Handle<HostKeyCheck>, key: &PublicKey) -> Result<Option<HashAlg>> {
+    if !key.algorithm().is_rsa() {
+        return Ok(None);
+    }
+    Ok(session.best_supported_rsa_hash().await?.flatten())
+}
+
+/// カンマ区切りの暗号方式を優先順の一覧に変換します
+fn parse_ciphers(ciphers: &str) -> Result<Vec<cipher::Name>> {
+    ciphers
+        .split(',')
+        .map(|name| {
+            cipher::Name::try_from(name.trim())
+                .map_err(|_| Error::Unsupported(format!("ネイティブ転送は暗号方式 '{}' に対応していません", name)))
+        })
+        .collect()
+}
+
+/// 接続文字列をユーザー名とホスト名に分割します
+///
+/// ユーザー名が省略されている場合は環境変数`USER`を使用します。
+fn split_connection(connection: &str) -> Result<(String, String)> {
+    match connection.split_once('@') {
+        Some((user, host)) => Ok((user.to_string(), host.to_string())),
+        None => {
+            let user = std::env::var("USER").map_err(|_| {
+                Error::Transport(format!("'{}' のユーザー名を特定できません", connection))
+            })?;
+            Ok((user, connection.to_string()))
+        }
+    }
+}
+
+/// SFTPのパスに変換します
+///
+/// SFTPの相対パスはホームディレクトリ基準のため、`~/`は取り除きます。
+fn sftp_path(remote: &str) -> String {
+    match remote {
+        "" | "~" => ".".to_string(),
+        _ => remote.strip_prefix("~/").unwrap_or(remote).to_string(),
+    }
+}
+
+/// リモートパスに名前を連結します
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+impl From<russh::Error> for Error {
+    fn from(e: russh::Error) -> Self {
+        Error::Transport(e.to_string())
+    }
+}
+
+impl From<russh_sftp::client::error::Error> for Error {
+    fn from(e: russh_sftp::client::error::Error) -> Self {
+        Error::Transport(e.to_string())
+    }
+}
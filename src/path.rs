@@ -1,9 +1,13 @@
-// パス管理とファイル転送機能
+// パス管理機能
 //
-// このモジュールは、ローカルおよびリモートパスのエイリアス管理と
-// SCPを使用したファイル転送機能を提供します。
+// このモジュールは、ローカルおよびリモートパスのエイリアス管理機能を提供します。
+// ファイル転送は transfer モジュールが担当します。
 
+use crate::browser;
 use crate::config::{Config, Path};
+use crate::remote;
+use crate::resolve::{self, AliasSource, Location};
+use crate::template;
 use colored::*;
 use std::collections::HashMap;
 use std::io::{self, Write};
@@ -80,232 +84,6 @@ pub fn remove_path(name: &str) -> Result<(), Box<dyn std::error::Error>> {
 }
 
 
-/// SCPを使用してファイルをコピーします
-/// 
-/// パスエイリアスとホストエイリアスを解決し、SCPコマンドを実行します。
-/// ローカル⇔リモート、リモート⇔ローカル、リモート⇔リモートのコピーに対応します。
-/// 
-/// # 引数
-/// * `src` - コピー元の指定（パスエイリアスまたは実際のパス）
-/// * `dst` - コピー先の指定（パスエイリアスまたは実際のパス）
-/// 
-/// # 戻り値
-/// 成功時は()、失敗時はエラーを返します。
-pub fn copy_files(src: &str, dst: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // 現在の設定を読み込み
-    let config = Config::load()?;
-
-    // コピー元とコピー先の詳細を解析
-    let (src_path, src_host) = parse_path_spec(src, &config)?;
-    let (dst_path, dst_host) = parse_path_spec(dst, &config)?;
-
-    println!("{}: {} から {} にコピー中...", "INFO".blue(), src, dst);
-
-    // SCPコマンドを構築
-    let mut cmd = std::process::Command::new("scp");
-    cmd.arg("-r"); // 再帰的コピーのオプション
-
-    // コピー元がローカルかどうかを事前に判定
-    let src_is_local = src_host.is_none();
-
-    // コピー元の設定
-    if let Some(ref host) = src_host {
-        // リモートホストからのコピーの場合
-        if let Some(host_config) = config.hosts.get(host) {
-            // エイリアスホストの場合：設定からポート番号と接続情報を取得
-            cmd.arg("-P").arg(host_config.port.to_string());
-            // 秘密鍵が指定されている場合は追加
-            if let Some(ref key_path) = host_config.key_path {
-                cmd.arg("-i").arg(key_path);
-            }
-            cmd.arg(format!("{}:{}", host_config.connection, src_path));
-        } else {
-            // 直接指定ホストの場合：デフォルトポート22を使用
-            cmd.arg("-P").arg("22");
-            cmd.arg(format!("{}:{}", host, src_path));
-        }
-    } else {
-        // ローカルファイルからのコピーの場合
-        let expanded_src = Config::expand_path(&src_path);
-        cmd.arg(expanded_src);
-    }
-
-    // コピー先の設定
-    if let Some(ref host) = dst_host {
-        // リモートホストへのコピーの場合
-        if let Some(host_config) = config.hosts.get(host) {
-            // エイリアスホストの場合：設定からポート番号と接続情報を取得
-            // コピー元がローカルの場合のみポート番号を指定
-            if src_is_local {
-                cmd.arg("-P").arg(host_config.port.to_string());
-                // 秘密鍵が指定されている場合は追加
-                if let Some(ref key_path) = host_config.key_path {
-                    cmd.arg("-i").arg(key_path);
-                }
-            }
-            cmd.arg(format!("{}:{}", host_config.connection, dst_path));
-        } else {
-            // 直接指定ホストの場合：デフォルトポート22を使用
-            // コピー元がローカルの場合のみポート番号を指定
-            if src_is_local {
-                cmd.arg("-P").arg("22");
-            }
-            cmd.arg(format!("{}:{}", host, dst_path));
-        }
-    } else {
-        // ローカルファイルへのコピーの場合
-        let expanded_dst = Config::expand_path(&dst_path);
-        cmd.arg(expanded_dst);
-    }
-
-    // SCPコマンドを実行
-    let status = cmd.status()?;
-
-    // 結果の表示
-    if status.success() {
-        println!("{}: コピーが正常に完了しました", "INFO".green());
-    } else {
-        println!("{}: コピーに失敗しました", "ERROR".red());
-    }
-
-    Ok(())
-}
-
-/// パス指定文字列を解析します
-/// 
-/// "host:path"形式の文字列を解析し、ホスト名とパスに分離します。
-/// パスエイリアスとホストエイリアスの解決も行います。
-/// 
-/// # 引数
-/// * `spec` - 解析するパス指定文字列
-/// * `config` - 現在の設定
-/// 
-/// # 戻り値
-/// (パス文字列, オプションのホスト名)のタプル、またはエラー
-fn parse_path_spec(spec: &str, config: &Config) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
-    // コロンが含まれる場合はリモートパスとして処理
-    if spec.contains(':') {
-        let parts: Vec<&str> = spec.splitn(2, ':').collect();
-        let host = parts[0].to_string();
-        let path = parts[1].to_string();
-
-        // ホスト名が設定に存在するかチェック
-        if config.hosts.contains_key(&host) {
-            // パス部分がパスエイリアスかチェック（旧形式との互換性）
-            if let Some(ref old_paths) = config.paths {
-                if old_paths.contains_key(&path) {
-                    let path_entry = &old_paths[&path];
-                    // リモートパスでない場合はエラー
-                    if !path_entry.is_remote {
-                        return Err(format!("パス '{}' はリモートパスではありません", path).into());
-                    }
-                    return Ok((path_entry.path.clone(), Some(host)));
-                }
-            }
-            // 直接パスの場合
-            return Ok((path, Some(host)));
-        }
-
-        // ケース2: ホスト名が直接のSSH接続文字列の可能性（user@hostname形式）
-        if host.contains('@') || is_valid_hostname(&host) {
-            // 直接SSH接続文字列として扱う
-            return Ok((path, Some(host)));
-        }
-
-        // ケース3: 不明なホスト形式
-        return Err(format!("ホスト '{}' が見つからず、有効なSSH接続文字列でもありません", host).into());
-    }
-
-    // コロンが含まれない場合はローカルパスまたはパスエイリアス（旧形式との互換性）
-    if let Some(ref old_paths) = config.paths {
-        if old_paths.contains_key(spec) {
-            let path_entry = &old_paths[spec];
-            // リモートパスの場合はホスト指定が必要
-            if path_entry.is_remote {
-                return Err(format!("パス '{}' はリモートパスですが、ホストが指定されていません", spec).into());
-            }
-            return Ok((path_entry.path.clone(), None));
-        }
-    }
-
-    // パスエイリアスでない場合は文字列をそのまま返す
-    Ok((spec.to_string(), None))
-}
-
-/// ホスト名が有効かどうかをチェックします
-/// 
-/// 基本的なホスト名の形式をチェックします（RFC準拠ではない簡易版）
-/// 
-/// # 引数
-/// * `hostname` - チェックするホスト名
-/// 
-/// # 戻り値
-/// 有効なホスト名の場合はtrue
-fn is_valid_hostname(hostname: &str) -> bool {
-    if hostname.is_empty() || hostname.len() > 253 {
-        return false;
-    }
-
-    // 基本的なホスト名の規則をチェック
-    // - 英数字とハイフン、ピリオドのみ
-    // - ハイフンで始まらない、終わらない
-    // - 連続するピリオドがない
-    let chars: Vec<char> = hostname.chars().collect();
-    
-    for (i, &ch) in chars.iter().enumerate() {
-        match ch {
-            'a'..='z' | 'A'..='Z' | '0'..='9' => continue,
-            '-' => {
-                if i == 0 || i == chars.len() - 1 {
-                    return false;
-                }
-            }
-            '.' => {
-                if i == 0 || i == chars.len() - 1 {
-                    return false;
-                }
-                if i > 0 && chars[i - 1] == '.' {
-                    return false;
-                }
-            }
-            _ => return false,
-        }
-    }
-    
-    // IPアドレスの場合も有効とする
-    if is_valid_ip_address(hostname) {
-        return true;
-    }
-    
-    true
-}
-
-/// IPアドレス（IPv4）が有効かどうかをチェックします
-/// 
-/// # 引数
-/// * `ip` - チェックするIPアドレス文字列
-/// 
-/// # 戻り値
-/// 有効なIPv4アドレスの場合はtrue
-fn is_valid_ip_address(ip: &str) -> bool {
-    let parts: Vec<&str> = ip.split('.').collect();
-    if parts.len() != 4 {
-        return false;
-    }
-    
-    for part in parts {
-        if let Ok(_num) = part.parse::<u8>() {
-            if part.len() > 1 && part.starts_with('0') {
-                return false; // 先頭ゼロは無効
-            }
-        } else {
-            return false;
-        }
-    }
-    
-    true
-}
-
 /// ローカルパスエイリアスを追加します
 pub fn add_local_path(name: &str, path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let mut config = Config::load()?;
@@ -345,6 +123,24 @@ pub fn add_host_path(host_name: &str, path_name: &str, path: &str) -> Result<(),
     Ok(())
 }
 
+/// 全ホスト共通のリモートパスエイリアスを追加します
+/// 
+/// ホスト別パスに同名のエイリアスがないホストで使用されます。
+pub fn add_remote_path(name: &str, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+    
+    if config.remote_paths.contains_key(name) {
+        println!("{}: 共通リモートパス '{}' は既に存在します", "WARN".yellow(), name);
+        return Ok(());
+    }
+    
+    config.remote_paths.insert(name.to_string(), path.to_string());
+    config.save()?;
+    
+    println!("{}: 共通リモートパス '{}' を追加しました", "INFO".green(), name);
+    Ok(())
+}
+
 /// 新しいパス一覧表示機能
 pub fn list_paths_new() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config::load()?;
@@ -353,24 +149,58 @@ pub fn list_paths_new() -> Result<(), Box<dyn std::error::Error>> {
     if !config.local_paths.is_empty() {
         println!("{}", "ローカルパス:".bold().green());
         for (name, path) in &config.local_paths {
-            let expanded_path = Config::expand_path(path);
+            let expanded_path = Config::expand_path(path)?;
             println!("  {} -> {}", name.cyan(), expanded_path);
         }
         println!();
     }
     
-    // ホスト別リモートパス表示
-    if !config.host_paths.is_empty() {
+    // 全ホスト共通のリモートパス表示
+    if !config.remote_paths.is_empty() {
+        println!("{}", "リモートパス（全ホスト共通）:".bold().yellow());
+        for (name, path) in &config.remote_paths {
+            println!("  {} -> {}", name.cyan(), path);
+        }
+        println!();
+    }
+    
+    // ホスト別の有効なリモートパス表示（定義元付き）
+    let mut host_names: Vec<&String> = config.hosts.keys().chain(config.host_paths.keys()).collect();
+    host_names.sort();
+    host_names.dedup();
+    let has_remote = !config.host_paths.is_empty() || !config.remote_paths.is_empty();
+    if has_remote && !host_names.is_empty() {
         println!("{}", "リモートパス（ホスト別）:".bold().yellow());
-        for (host_name, paths) in &config.host_paths {
+        for host_name in host_names {
+            let aliases = resolve::remote_aliases(&config, host_name);
+            if aliases.is_empty() {
+                continue;
+            }
             println!("  {}:", host_name.cyan().bold());
-            for (path_name, path) in paths {
-                println!("    {} -> {}", path_name.cyan(), path);
+            for (path_name, (path, source)) in aliases {
+                let source = match source {
+                    AliasSource::Host if config.remote_paths.contains_key(&path_name) => "ホスト固有、共通を上書き",
+                    AliasSource::Host => "ホスト固有",
+                    AliasSource::Global => "共通",
+                    AliasSource::Legacy => "旧形式",
+                };
+                println!("    {} -> {} {}", path_name.cyan(), path, format!("({})", source).dimmed());
             }
         }
         println!();
     }
     
+    // エイリアスごとの除外パターン表示
+    if !config.excludes.is_empty() {
+        println!("{}", "除外パターン:".bold().magenta());
+        let mut names: Vec<&String> = config.excludes.keys().collect();
+        names.sort();
+        for name in names {
+            println!("  {} -> {}", name.cyan(), config.excludes[name].join(" "));
+        }
+        println!();
+    }
+    
     // 旧形式のパス表示（互換性）
     if let Some(ref old_paths) = config.paths {
         if !old_paths.is_empty() {
@@ -378,7 +208,7 @@ pub fn list_paths_new() -> Result<(), Box<dyn std::error::Error>> {
             for (name, path) in old_paths {
                 let path_type = if path.is_remote { "リモート" } else { "ローカル" };
                 let expanded_path = if !path.is_remote {
-                    Config::expand_path(&path.path)
+                    Config::expand_path(&path.path)?
                 } else {
                     path.path.clone()
                 };
@@ -387,7 +217,7 @@ pub fn list_paths_new() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
     
-    if config.local_paths.is_empty() && config.host_paths.is_empty() && 
+    if config.local_paths.is_empty() && config.host_paths.is_empty() && config.remote_paths.is_empty() &&
        (config.paths.is_none() || config.paths.as_ref().unwrap().is_empty()) {
         println!("設定されているパスはありません");
     }
@@ -396,14 +226,18 @@ pub fn list_paths_new() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 /// インタラクティブにパスを追加します
-pub fn add_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
+/// 
+/// # 引数
+/// * `verify` - リモートパスの存在をssh経由で確認するかどうか
+pub fn add_path_interactive(verify: bool) -> Result<(), Box<dyn std::error::Error>> {
     println!("{}", "=== インタラクティブ パス追加 ===".bold().blue());
     
     // パスタイプの選択
     println!("パスタイプを選択してください:");
     println!("1. ローカルパス");
     println!("2. リモートパス（ホスト別）");
-    print!("選択 [1-2]: ");
+    println!("3. リモートパス（全ホスト共通）");
+    print!("選択 [1-3]: ");
     io::stdout().flush()?;
     
     let mut choice = String::new();
@@ -412,7 +246,8 @@ pub fn add_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
     
     match choice {
         "1" => add_local_path_interactive(),
-        "2" => add_remote_path_interactive(),
+        "2" => add_remote_path_interactive(verify),
+        "3" => add_global_remote_path_interactive(),
         _ => {
             println!("{}: 無効な選択です", "ERROR".red());
             Ok(())
@@ -471,7 +306,7 @@ fn add_local_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 /// インタラクティブにリモートパスを追加します
-fn add_remote_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
+fn add_remote_path_interactive(verify: bool) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n{}", "--- リモートパス追加 ---".bold());
     
     // 利用可能なホストを表示
@@ -510,6 +345,347 @@ fn add_remote_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
     
+    // パスの入力（末尾の?でリモートの候補を表示）
+    let endpoint = resolve::resolve_host(&config, host_name)?;
+    let path = read_remote_path(&endpoint)?;
+    let path = path.as_str();
+    
+    if path.is_empty() {
+        println!("{}: パスは必須です", "ERROR".red());
+        return Ok(());
+    }
+    
+    // リモートパスの存在確認
+    if verify && !verify_remote_path(&endpoint, path)? {
+        print!("パスが見つかりません。それでも追加しますか？ [y/N]: ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("{}: キャンセルされました", "INFO".yellow());
+            return Ok(());
+        }
+    }
+    
+    // 確認表示
+    println!("\n{}", "=== 設定確認 ===".bold());
+    println!("ホスト: {}", host_name.cyan());
+    println!("パス名: {}", path_name.cyan());
+    println!("パス: {}", path);
+    println!("タイプ: {}", "リモート".yellow());
+    
+    print!("\nこの設定で追加しますか？ [y/N]: ");
+    io::stdout().flush()?;
+    let mut confirm = String::new();
+    io::stdin().read_line(&mut confirm)?;
+    let confirm = confirm.trim().to_lowercase();
+    
+    if confirm == "y" || confirm == "yes" {
+        add_host_path(host_name, path_name, path)?;
+        println!("{}: インタラクティブ追加が完了しました", "SUCCESS".green());
+    } else {
+        println!("{}: キャンセルされました", "INFO".yellow());
+    }
+    
+    Ok(())
+}
+
+/// パスエイリアスのデフォルトの除外パターンを追加します
+/// 
+/// エイリアスをコピー元に使用した場合、これらのパターンに一致するファイルは転送されません。
+/// 
+/// # 引数
+/// * `alias` - パスのエイリアス名
+/// * `patterns` - 追加する除外パターン
+/// 
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn add_excludes(alias: &str, patterns: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+
+    let excludes = config.excludes.entry(alias.to_string()).or_default();
+    for pattern in patterns {
+        if excludes.contains(pattern) {
+            println!("{}: パターン '{}' は既に追加されています", "WARN".yellow(), pattern);
+        } else {
+            excludes.push(pattern.clone());
+        }
+    }
+    config.save()?;
+
+    println!("{}: エイリアス '{}' の除外パターンを更新しました", "INFO".green(), alias);
+    Ok(())
+}
+
+/// パスエイリアスのデフォルトの除外パターンを削除します
+/// 
+/// # 引数
+/// * `alias` - パスのエイリアス名
+/// * `patterns` - 削除する除外パターン（空の場合は全て削除）
+/// 
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn remove_excludes(alias: &str, patterns: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+
+    let Some(excludes) = config.excludes.get_mut(alias) else {
+        println!("{}: エイリアス '{}' に除外パターンはありません", "ERROR".red(), alias);
+        return Ok(());
+    };
+    if patterns.is_empty() {
+        excludes.clear();
+    } else {
+        for pattern in patterns {
+            if !excludes.contains(pattern) {
+                println!("{}: パターン '{}' が見つかりません", "WARN".yellow(), pattern);
+            }
+        }
+        excludes.retain(|p| !patterns.contains(p));
+    }
+    if excludes.is_empty() {
+        config.excludes.remove(alias);
+    }
+    config.save()?;
+
+    println!("{}: エイリアス '{}' の除外パターンを更新しました", "INFO".green(), alias);
+    Ok(())
+}
+
+/// パステンプレートのユーザー変数を追加します
+/// 
+/// 名前には英数字と `_`・`-`・`.` のみを使用できます。組み込み変数と同じ名前は使用できません。
+/// 
+/// # 引数
+/// * `name` - 変数名
+/// * `value` - 変数の値
+/// 
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn add_variable(name: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !template::is_variable_name(name) || template::BUILTIN_VARIABLES.contains(&name) {
+        println!("{}: 変数名 '{}' は使用できません", "ERROR".red(), name);
+        return Ok(());
+    }
+
+    let mut config = Config::load()?;
+
+    if config.variables.contains_key(name) {
+        println!("{}: 変数 '{}' は既に存在します", "WARN".yellow(), name);
+        return Ok(());
+    }
+
+    config.variables.insert(name.to_string(), value.to_string());
+    config.save()?;
+
+    println!("{}: 変数 '{}' を追加しました", "INFO".green(), name);
+    Ok(())
+}
+
+/// パステンプレートのユーザー変数を削除します
+pub fn remove_variable(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = Config::load()?;
+
+    if config.variables.remove(name).is_none() {
+        println!("{}: 変数 '{}' が見つかりません", "ERROR".red(), name);
+        return Ok(());
+    }
+    config.save()?;
+
+    println!("{}: 変数 '{}' を削除しました", "INFO".green(), name);
+    Ok(())
+}
+
+/// パステンプレートの変数一覧を表示します
+pub fn list_variables() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+
+    println!("{}", "組み込み変数:".bold().green());
+    println!("  {{date}} {{time}} {{host}} {{user}} {{env:VAR}} {{cwd_basename}} {{git_branch}}");
+
+    if !config.variables.is_empty() {
+        println!();
+        println!("{}", "ユーザー変数:".bold().yellow());
+        for (name, value) in &config.variables {
+            println!("  {} -> {}", name.cyan(), value);
+        }
+    }
+
+    Ok(())
+}
+
+/// パス指定を解決した結果を表示します
+/// 
+/// エイリアス、サブパス、テンプレート変数を展開した実際のパスを表示します。
+/// 
+/// # 引数
+/// * `spec` - パス指定（エイリアスまたはhost:path）
+/// 
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn show_resolved(spec: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+
+    match resolve::resolve_spec(&config, spec)? {
+        Location::Local(path) => {
+            println!("{} ({}) -> {}", spec.cyan(), "ローカル".green(), path);
+        }
+        Location::Remote { endpoint, path } => {
+            println!(
+                "{} ({}) -> {}:{} {}",
+                spec.cyan(),
+                "リモート".yellow(),
+                endpoint.connection,
+                path,
+                format!("(port: {})", endpoint.port).dimmed()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// リモートパスを入力します
+/// 
+/// 入力の末尾に`?`を付けると、その続きとなるリモートのパス候補をssh経由で表示し、
+/// 再度入力を求めます。空のまま入力するとリモートディレクトリブラウザを起動します。
+fn read_remote_path(endpoint: &resolve::Endpoint) -> Result<String, Box<dyn std::error::Error>> {
+    loop {
+        print!("リモートパス（末尾に?で候補を表示、空欄でブラウズ）: ");
+        io::stdout().flush()?;
+        let mut path = String::new();
+        io::stdin().read_line(&mut path)?;
+        let path = path.trim();
+        
+        if path.is_empty() {
+            return Ok(browser::browse(endpoint, "")?.unwrap_or_default());
+        }
+        
+        let Some(prefix) = path.strip_suffix('?') else {
+            return Ok(path.to_string());
+        };
+        
+        let candidates = remote::complete_path(endpoint, prefix)?;
+        if candidates.is_empty() {
+            println!("  {}", "候補がありません".dimmed());
+        }
+        for candidate in candidates {
+            println!("  {}", candidate);
+        }
+    }
+}
+
+/// リモートパスの存在を確認して結果を表示します
+/// 
+/// # 戻り値
+/// パスが存在する場合はtrue
+fn verify_remote_path(endpoint: &resolve::Endpoint, path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let status = remote::check_paths(endpoint, &[path.to_string()])?.remove(0);
+    match (status.kind, status.permissions) {
+        (Some(kind), Some(permissions)) => {
+            println!("{}: {} ({}, {})", "OK".green(), path, kind.label(), permissions);
+            Ok(true)
+        }
+        _ => {
+            println!("{}: {} が見つかりません", "WARN".yellow(), path);
+            Ok(false)
+        }
+    }
+}
+
+/// ホスト別リモートパスの存在を確認します
+/// 
+/// 各ホストで有効なリモートパスエイリアス（共通パスを含む）を解決し、
+/// ssh経由で存在・種類・パーミッションを確認して表示します。
+/// 
+/// # 引数
+/// * `host` - 確認するホスト（Noneの場合は全ホスト）
+/// 
+/// # 戻り値
+/// 全てのパスが存在する場合は()、見つからないパスがある場合はエラーを返します。
+pub fn check_paths(host: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+    
+    let mut host_names: Vec<&String> = match host {
+        Some(name) => match config.hosts.get_key_value(name) {
+            Some((name, _)) => vec![name],
+            None => {
+                println!("{}: ホスト '{}' が見つかりません", "ERROR".red(), name);
+                return Ok(());
+            }
+        },
+        None => config.hosts.keys().collect(),
+    };
+    host_names.sort();
+    
+    let mut missing = 0;
+    for host_name in host_names {
+        let aliases = resolve::remote_aliases(&config, host_name);
+        if aliases.is_empty() {
+            continue;
+        }
+        println!("{}:", host_name.cyan().bold());
+        
+        // テンプレートを含めてエイリアスを解決
+        let mut names = Vec::new();
+        let mut paths = Vec::new();
+        for name in aliases.keys() {
+            match resolve::resolve_spec(&config, &format!("{}:{}", host_name, name)) {
+                Ok(location) => {
+                    names.push(name);
+                    paths.push(location.path().to_string());
+                }
+                Err(e) => println!("  {} {} ({})", "✗".red(), name.cyan(), e),
+            }
+        }
+        
+        let endpoint = resolve::resolve_host(&config, host_name)?;
+        let statuses = match remote::check_paths(&endpoint, &paths) {
+            Ok(statuses) => statuses,
+            Err(e) => {
+                println!("  {}: {}", "ERROR".red(), e);
+                continue;
+            }
+        };
+        
+        for (name, status) in names.into_iter().zip(statuses) {
+            match (status.kind, status.permissions) {
+                (Some(kind), Some(permissions)) => println!(
+                    "  {} {} -> {} {}",
+                    "✓".green(),
+                    name.cyan(),
+                    status.path,
+                    format!("({}, {})", kind.label(), permissions).dimmed()
+                ),
+                _ => {
+                    missing += 1;
+                    println!("  {} {} -> {} {}", "✗".red(), name.cyan(), status.path, "(見つかりません)".red());
+                }
+            }
+        }
+    }
+    
+    if missing > 0 {
+        return Err(format!("{} 個のパスが見つかりません", missing).into());
+    }
+    Ok(())
+}
+
+/// インタラクティブに全ホスト共通のリモートパスを追加します
+fn add_global_remote_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
+    println!("\n{}", "--- 共通リモートパス追加 ---".bold());
+    
+    // パス名の入力
+    print!("パス名（エイリアス）: ");
+    io::stdout().flush()?;
+    let mut path_name = String::new();
+    io::stdin().read_line(&mut path_name)?;
+    let path_name = path_name.trim();
+    
+    if path_name.is_empty() {
+        println!("{}: パス名は必須です", "ERROR".red());
+        return Ok(());
+    }
+    
     // パスの入力
     print!("リモートパス: ");
     io::stdout().flush()?;
@@ -524,10 +700,9 @@ fn add_remote_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
     
     // 確認表示
     println!("\n{}", "=== 設定確認 ===".bold());
-    println!("ホスト: {}", host_name.cyan());
     println!("パス名: {}", path_name.cyan());
     println!("パス: {}", path);
-    println!("タイプ: {}", "リモート".yellow());
+    println!("タイプ: {}", "リモート（全ホスト共通）".yellow());
     
     print!("\nこの設定で追加しますか？ [y/N]: ");
     io::stdout().flush()?;
@@ -536,11 +711,11 @@ fn add_remote_path_interactive() -> Result<(), Box<dyn std::error::Error>> {
     let confirm = confirm.trim().to_lowercase();
     
     if confirm == "y" || confirm == "yes" {
-        add_host_path(host_name, path_name, path)?;
+        add_remote_path(path_name, path)?;
         println!("{}: インタラクティブ追加が完了しました", "SUCCESS".green());
     } else {
         println!("{}: キャンセルされました", "INFO".yellow());
     }
     
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,278 @@
+// 転送の進捗表示機能
+//
+// このモジュールは、転送中のプログレスバー（転送量・速度・残り時間）、
+// ローカルソースの事前スキャン、およびrsyncの進捗出力の解析を提供します。
+
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// プログレスバーの再描画間隔
+const DRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// プログレスバーの幅（文字数）
+const BAR_WIDTH: usize = 24;
+
+/// 転送中に発生する進捗イベント
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum ProgressEvent<'a> {
+    /// ファイルの転送を開始した
+    FileStarted {
+        /// 転送中のファイルのパス
+        path: &'a Path,
+        /// ファイルサイズ（バイト）
+        size: u64,
+    },
+    /// 指定バイト数を転送した
+    Transferred(u64),
+    /// 途中から再開したため、指定バイト数の転送を省略した（転送量には含めない）
+    Resumed(u64),
+    /// ファイルの転送と検証が完了した
+    FileFinished,
+}
+
+/// ローカルソースの事前スキャン結果
+#[derive(Debug, Clone, Default)]
+pub struct LocalScan {
+    /// スキャンされたファイルとサイズの一覧
+    pub files: Vec<(PathBuf, u64)>,
+}
+
+impl LocalScan {
+    /// ファイルサイズの合計を返します
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// ローカルのファイルまたはディレクトリを再帰的にスキャンします
+///
+/// シンボリックリンクは辿りません。
+///
+/// # 引数
+/// * `path` - スキャンするパス
+///
+/// # 戻り値
+/// スキャン結果、またはエラー
+pub fn scan_local(path: &Path) -> io::Result<LocalScan> {
+    let mut scan = LocalScan::default();
+    scan_entry(path, &mut scan)?;
+    Ok(scan)
+}
+
+/// 1エントリを再帰的にスキャンします
+fn scan_entry(path: &Path, scan: &mut LocalScan) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        let mut entries: Vec<_> = fs::read_dir(path)?.collect::<io::Result<_>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            scan_entry(&entry.path(), scan)?;
+        }
+    } else {
+        scan.files.push((path.to_path_buf(), meta.len()));
+    }
+    Ok(())
+}
+
+/// 転送のプログレスバー
+///
+/// 標準エラー出力が端末の場合のみ描画し、それ以外では集計だけを行います。
+pub struct ProgressBar {
+    total: Option<u64>,
+    position: u64,
+    skipped: u64,
+    files: u64,
+    started: Instant,
+    last_draw: Option<Instant>,
+    enabled: bool,
+}
+
+impl ProgressBar {
+    /// 新しいプログレスバーを作成します
+    ///
+    /// # 引数
+    /// * `total` - 転送予定の合計バイト数（不明な場合はNone）
+    pub fn new(total: Option<u64>) -> Self {
+        ProgressBar {
+            total,
+            position: 0,
+            skipped: 0,
+            files: 0,
+            started: Instant::now(),
+            last_draw: None,
+            enabled: io::stderr().is_terminal(),
+        }
+    }
+
+    /// 進捗イベントを反映します
+    #[cfg(feature = "native")]
+    pub fn handle(&mut self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::FileStarted { .. } => {}
+            ProgressEvent::Transferred(n) => self.position += n,
+            ProgressEvent::Resumed(n) => self.skipped += n,
+            ProgressEvent::FileFinished => self.files += 1,
+        }
+        self.draw(false);
+    }
+
+    /// 転送済みバイト数を設定します
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+        self.draw(false);
+    }
+
+    /// 転送済みファイル数を設定します
+    pub fn set_files(&mut self, files: u64) {
+        self.files = files;
+    }
+
+    /// 転送済みバイト数を返します
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// 転送済みファイル数を返します
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// 転送開始からの経過時間を返します
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// プログレスバーを消してからメッセージを1行表示します
+    pub fn println(&mut self, message: &str) {
+        self.clear();
+        eprintln!("{}", message);
+        self.draw(true);
+    }
+
+    /// プログレスバーの表示を消去して終了します
+    pub fn finish(&mut self) {
+        self.clear();
+        self.enabled = false;
+    }
+
+    /// 表示中の行を消去します
+    fn clear(&self) {
+        if self.enabled && self.last_draw.is_some() {
+            eprint!("\r\x1b[2K");
+        }
+    }
+
+    /// プログレスバーを描画します
+    ///
+    /// `force`がfalseの場合は再描画間隔を空けて描画します。
+    fn draw(&mut self, force: bool) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if !force && self.last_draw.is_some_and(|t| now - t < DRAW_INTERVAL) {
+            return;
+        }
+        self.last_draw = Some(now);
+
+        // 速度は実際に転送した量から求め、再開で省略した分は完了済みとして扱う
+        let rate = rate(self.position, self.elapsed());
+        let done = self.position + self.skipped;
+        let line = match self.total {
+            Some(total) if total > 0 => {
+                let ratio = (done as f64 / total as f64).min(1.0);
+                let filled = (ratio * BAR_WIDTH as f64) as usize;
+                let eta = if rate > 0.0 {
+                    format_duration(Duration::from_secs_f64(
+                        total.saturating_sub(done) as f64 / rate,
+                    ))
+                } else {
+                    "--:--".to_string()
+                };
+                format!(
+                    "[{}{}] {:>3}% {} / {}  {}/s  ETA {}",
+                    "#".repeat(filled),
+                    ".".repeat(BAR_WIDTH - filled),
+                    (ratio * 100.0) as u32,
+                    format_bytes(done),
+                    format_bytes(total),
+                    format_bytes(rate as u64),
+                    eta
+                )
+            }
+            _ => format!(
+                "{}  {}/s  {}",
+                format_bytes(self.position),
+                format_bytes(rate as u64),
+                format_duration(self.elapsed())
+            ),
+        };
+        eprint!("\r\x1b[2K{}", line);
+        let _ = io::stderr().flush();
+    }
+}
+
+/// 転送速度（バイト/秒）を計算します
+pub fn rate(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        bytes as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/// バイト数を人間が読みやすい形式に変換します
+///
+/// # 引数
+/// * `bytes` - バイト数
+///
+/// # 戻り値
+/// "1.5 MiB"のような文字列
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// 時間を "H:MM:SS" または "M:SS" 形式に変換します
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// rsyncの `--info=progress2` の進捗行を解析します
+///
+/// "  1,234,567  45%  1.23MB/s  0:00:12 (xfr#3, to-chk=5/10)" のような行から
+/// 転送済みバイト数と、含まれていれば転送済みファイル数を取り出します。
+///
+/// # 戻り値
+/// 進捗行の場合は (転送済みバイト数, 転送済みファイル数)
+pub fn parse_rsync_progress(line: &str) -> Option<(u64, Option<u64>)> {
+    let mut fields = line.split_whitespace();
+    let bytes = fields.next()?.replace(',', "").parse::<u64>().ok()?;
+    if !fields.next()?.ends_with('%') {
+        return None;
+    }
+    let files = line
+        .split_once("xfr#")
+        .and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|n| n.parse().ok());
+    Some((bytes, files))
+}
@@ -0,0 +1,501 @@
+// リモート操作機能
+//
+// このモジュールは、ssh経由でリモートホスト上のパスの存在確認や
+// ディレクトリ一覧の取得、ファイルの読み書きを行います。
+
+use crate::argv;
+use crate::diff::FileDigest;
+use crate::error::{Error, Result};
+use crate::resolve::Endpoint;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::process::Stdio;
+
+/// リモートパスの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    /// ディレクトリ
+    Dir,
+    /// 通常のファイル
+    File,
+    /// シンボリックリンク
+    Symlink,
+    /// その他（デバイスファイルなど）
+    Other,
+}
+
+impl PathKind {
+    /// `ls -l` のモード文字列の先頭文字から種類を判定します
+    fn from_mode(mode: &str) -> Self {
+        match mode.chars().next() {
+            Some('d') => PathKind::Dir,
+            Some('-') => PathKind::File,
+            Some('l') => PathKind::Symlink,
+            _ => PathKind::Other,
+        }
+    }
+
+    /// 表示用の名前を返します
+    pub fn label(&self) -> &'static str {
+        match self {
+            PathKind::Dir => "ディレクトリ",
+            PathKind::File => "ファイル",
+            PathKind::Symlink => "シンボリックリンク",
+            PathKind::Other => "その他",
+        }
+    }
+}
+
+/// リモートパスの確認結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathStatus {
+    /// 確認したパス
+    pub path: String,
+    /// パスの種類（存在しない場合はNone）
+    pub kind: Option<PathKind>,
+    /// パーミッション（例: "drwxr-xr-x"、存在しない場合はNone）
+    pub permissions: Option<String>,
+}
+
+/// リモートでシェルコマンドを実行し、標準出力を返します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `command` - リモートシェルで実行するコマンド文字列
+///
+/// # 戻り値
+/// 終了コード0の場合は標準出力、それ以外はエラー
+pub fn run(endpoint: &Endpoint, command: &str) -> Result<String> {
+    let output = run_bytes(endpoint, command)?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+/// リモートでシェルコマンドを実行し、標準出力をバイト列で返します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `command` - リモートシェルで実行するコマンド文字列
+///
+/// # 戻り値
+/// 終了コード0の場合は標準出力、それ以外はエラー
+pub fn run_bytes(endpoint: &Endpoint, command: &str) -> Result<Vec<u8>> {
+    let output = argv::command(&argv::ssh_exec_argv(endpoint, command))
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(command_failed(endpoint, &output.stderr));
+    }
+    Ok(output.stdout)
+}
+
+/// リモートでシェルコマンドを実行し、標準入力にデータを渡します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `command` - リモートシェルで実行するコマンド文字列
+/// * `input` - 標準入力に渡すデータ
+pub fn run_with_input(endpoint: &Endpoint, command: &str, input: &[u8]) -> Result<()> {
+    let (status, stderr) = exec_with_input(endpoint, command, input)?;
+    if status != Some(0) {
+        return Err(command_failed(endpoint, &stderr));
+    }
+    Ok(())
+}
+
+/// リモートでシェルコマンドを実行し、標準入力にデータを渡して終了コードを返します
+///
+/// コマンドが入力を読み切らずに終了した場合の書き込みエラーは無視します。
+///
+/// # 戻り値
+/// 終了コード（シグナルで終了した場合はNone）と標準エラー出力
+fn exec_with_input(endpoint: &Endpoint, command: &str, input: &[u8]) -> Result<(Option<i32>, Vec<u8>)> {
+    let mut child = argv::command(&argv::ssh_exec_argv(endpoint, command))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = stdin.write_all(input) {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(e.into());
+            }
+        }
+    }
+    let output = child.wait_with_output()?;
+    Ok((output.status.code(), output.stderr))
+}
+
+/// 端末を割り当てたsshセッションでコマンドを実行します
+///
+/// 標準入出力は引き継がれるため、`sudo` のパスワード入力に使用できます。
+/// `timestamp_type=tty` の環境ではセッションごとに認証が必要になるため、
+/// 1つの操作は1回のセッションで完結させてください。
+///
+/// # 戻り値
+/// 終了コード（シグナルで終了した場合はNone）
+fn run_tty(endpoint: &Endpoint, command: &str) -> Result<Option<i32>> {
+    let mut ssh_argv = argv::ssh_exec_argv(endpoint, command);
+    ssh_argv.insert(1, "-t".to_string());
+    Ok(argv::command(&ssh_argv).status()?.code())
+}
+
+/// コマンド失敗時のエラーを作成します
+fn command_failed(endpoint: &Endpoint, stderr: &[u8]) -> Error {
+    Error::Transport(format!(
+        "'{}' でのコマンド実行に失敗しました: {}",
+        endpoint.connection,
+        String::from_utf8_lossy(stderr).trim()
+    ))
+}
+
+/// 書き込み前に内容を確認したファイルが変更されていた場合の終了コード
+const CHANGED_EXIT_CODE: i32 = 3;
+
+/// 書き込み先が `$e`（SHA-256、空の場合は確認しない）と一致するかを確認するスクリプト
+const CHECK_UNCHANGED_SCRIPT: &str = "if [ -n \"$e\" ]; then \
+     if command -v sha256sum >/dev/null 2>&1; then h=$(sha256sum < \"$f\"); else h=$(shasum -a 256 < \"$f\"); fi; \
+     [ \"${h%% *}\" = \"$e\" ] || exit 3; fi";
+
+/// リモートに所有者のみ読み書きできる作業用の一時ファイルを作成します
+///
+/// # 戻り値
+/// 作成した一時ファイルのパス
+fn remote_temp_file(endpoint: &Endpoint) -> Result<String> {
+    let path = run(endpoint, "umask 077 && mktemp")?.trim().to_string();
+    if path.is_empty() {
+        return Err(command_failed(endpoint, b"mktemp failed"));
+    }
+    Ok(path)
+}
+
+/// リモートの一時ファイルを削除します（失敗は無視します）
+fn remove_temp_file(endpoint: &Endpoint, temp: &str) {
+    let _ = run(endpoint, &format!("rm -f -- {}", argv::quote_literal(temp)));
+}
+
+/// リモートファイルの内容を取得します
+///
+/// `sudo` が指定された場合は、端末を割り当てたセッションで `sudo cat` を実行し、
+/// 自分が所有する一時ファイルに書き出してから読み込みます。
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `path` - ファイルのパス
+/// * `sudo` - `sudo` 経由で読み込むかどうか
+pub fn read_file(endpoint: &Endpoint, path: &str, sudo: bool) -> Result<Vec<u8>> {
+    if !sudo {
+        return run_bytes(endpoint, &format!("cat -- {}", argv::quote_literal(path)));
+    }
+
+    let temp = remote_temp_file(endpoint)?;
+    let quoted_temp = argv::quote_literal(&temp);
+    let result = run_tty(
+        endpoint,
+        &format!("sudo cat -- {} > {}", argv::quote_literal(path), quoted_temp),
+    )
+    .and_then(|code| match code {
+        Some(0) => run_bytes(endpoint, &format!("cat -- {}", quoted_temp)),
+        _ => Err(Error::Transport(format!(
+            "'{}' で sudo による読み込みに失敗しました",
+            endpoint.connection
+        ))),
+    });
+    remove_temp_file(endpoint, &temp);
+    result
+}
+
+/// リモートファイルに内容を書き込みます
+///
+/// `sudo` が指定された場合は、内容を自分が所有する一時ファイルに送ってから、
+/// 端末を割り当てた1回のセッションで変更の確認と `sudo tee` による書き込みを行います。
+/// 指定しない場合は同じディレクトリの一時ファイルに書き込んでから `mv` で置き換えるため、
+/// 途中で失敗しても元のファイルが壊れることはありません。
+/// いずれの場合も既存ファイルの所有者とパーミッションは維持されます。
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `path` - ファイルのパス
+/// * `data` - 書き込む内容
+/// * `sudo` - `sudo tee` 経由で書き込むかどうか
+/// * `expected` - 書き込み前の内容のSHA-256（一致しない場合は書き込まない）
+///
+/// # 戻り値
+/// 書き込んだ場合はtrue、`expected` と一致せず書き込まなかった場合はfalse
+pub fn write_file(
+    endpoint: &Endpoint,
+    path: &str,
+    data: &[u8],
+    sudo: bool,
+    expected: Option<&str>,
+) -> Result<bool> {
+    let path = argv::quote_literal(path);
+    let expected = expected.unwrap_or("");
+
+    if !sudo {
+        // シンボリックリンクはリンク先を置き換え、`cp -p` で所有者とパーミッションを引き継ぐ
+        let command = format!(
+            "f={}; e={}; [ -L \"$f\" ] && f=$(readlink -f -- \"$f\"); {}; \
+             t=$(mktemp \"$(dirname -- \"$f\")/.sshportal.XXXXXX\") || exit 1; \
+             [ -e \"$f\" ] && cp -p -- \"$f\" \"$t\"; \
+             if cat > \"$t\" && mv -f -- \"$t\" \"$f\"; then exit 0; fi; rm -f -- \"$t\"; exit 1",
+            path, expected, CHECK_UNCHANGED_SCRIPT
+        );
+        return match exec_with_input(endpoint, &command, data)? {
+            (Some(0), _) => Ok(true),
+            (Some(CHANGED_EXIT_CODE), _) => Ok(false),
+            (_, stderr) => Err(command_failed(endpoint, &stderr)),
+        };
+    }
+
+    let temp = remote_temp_file(endpoint)?;
+    let result = run_with_input(endpoint, &format!("cat > {}", argv::quote_literal(&temp)), data)
+        .and_then(|_| {
+            let script = format!("f=\"$1\"; e=\"$3\"; {}; tee -- \"$f\" < \"$2\" > /dev/null", CHECK_UNCHANGED_SCRIPT);
+            run_tty(
+                endpoint,
+                &format!(
+                    "sudo sh -c {} sh {} {} {}",
+                    argv::shell_quote(&script),
+                    path,
+                    argv::quote_literal(&temp),
+                    argv::shell_quote(expected)
+                ),
+            )
+        })
+        .and_then(|code| match code {
+            Some(0) => Ok(true),
+            Some(CHANGED_EXIT_CODE) => Ok(false),
+            _ => Err(Error::Transport(format!(
+                "'{}' で sudo による書き込みに失敗しました",
+                endpoint.connection
+            ))),
+        });
+    remove_temp_file(endpoint, &temp);
+    result
+}
+
+/// 複数のリモートパスの存在と種類、パーミッションを1回のssh接続で確認します
+///
+/// `stat`はGNUとBSDで書式指定が異なるため、`ls -ld`の出力を使用します。
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `paths` - 確認するパスの一覧
+///
+/// # 戻り値
+/// パスごとの確認結果（引数と同じ順序）
+pub fn check_paths(endpoint: &Endpoint, paths: &[String]) -> Result<Vec<PathStatus>> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let script: Vec<String> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let path = argv::quote_literal(path);
+            format!(
+                "if [ -e {p} ] || [ -L {p} ]; then echo \"{i} $(ls -ld -- {p} | cut -c1-10)\"; else echo \"{i} -\"; fi",
+                p = path,
+                i = i
+            )
+        })
+        .collect();
+    let output = run(endpoint, &script.join("; "))?;
+
+    let mut statuses: Vec<PathStatus> = paths
+        .iter()
+        .map(|path| PathStatus {
+            path: path.clone(),
+            kind: None,
+            permissions: None,
+        })
+        .collect();
+    for line in output.lines() {
+        let Some((index, mode)) = line.split_once(' ') else { continue };
+        let Some(status) = index.parse::<usize>().ok().and_then(|i| statuses.get_mut(i)) else {
+            continue;
+        };
+        if mode != "-" {
+            status.kind = Some(PathKind::from_mode(mode));
+            status.permissions = Some(mode.to_string());
+        }
+    }
+    Ok(statuses)
+}
+
+/// リモートのパス候補を一覧します
+///
+/// 入力途中のパスに続く候補を返します。ディレクトリには末尾に`/`が付きます。
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `prefix` - 入力途中のパス（空の場合はホームディレクトリ直下）
+///
+/// # 戻り値
+/// 候補パスの一覧（見つからない場合は空）
+pub fn complete_path(endpoint: &Endpoint, prefix: &str) -> Result<Vec<String>> {
+    let pattern = format!("{}*", argv::quote_literal(prefix));
+    let output = run(endpoint, &format!("ls -dp -- {} 2>/dev/null || true", pattern))?;
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+/// リモートディレクトリの内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirListing {
+    /// ディレクトリの絶対パス
+    pub path: String,
+    /// エントリ名の一覧（ディレクトリには末尾に`/`が付く）
+    pub entries: Vec<String>,
+}
+
+/// リモートディレクトリの内容を一覧します
+///
+/// 隠しファイルを含め、`.`と`..`は除きます。
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `dir` - 一覧するディレクトリ（`~`で始まるパスも可）
+///
+/// # 戻り値
+/// ディレクトリの絶対パスとエントリ一覧
+pub fn list_dir(endpoint: &Endpoint, dir: &str) -> Result<DirListing> {
+    let output = run(endpoint, &format!("cd -- {} && pwd && ls -Ap", argv::quote_literal(dir)))?;
+    let mut lines = output.lines();
+    let path = lines.next().unwrap_or(dir).to_string();
+    Ok(DirListing {
+        path,
+        entries: lines.map(str::to_string).collect(),
+    })
+}
+
+/// リモートファイルの先頭部分を取得します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `path` - ファイルのパス
+/// * `lines` - 取得する行数
+pub fn preview(endpoint: &Endpoint, path: &str, lines: usize) -> Result<String> {
+    run(endpoint, &format!("head -n {} -- {}", lines, argv::quote_literal(path)))
+}
+
+/// リモートパスの種類を、シンボリックリンクを辿って判定します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `path` - 確認するパス
+///
+/// # 戻り値
+/// ディレクトリまたはファイルの場合はその種類、存在しない場合はNone
+pub fn stat_kind(endpoint: &Endpoint, path: &str) -> Result<Option<PathKind>> {
+    let path = argv::quote_literal(path);
+    let output = run(
+        endpoint,
+        &format!(
+            "if [ -d {p} ]; then echo d; elif [ -f {p} ]; then echo f; elif [ -e {p} ]; then echo o; else echo -; fi",
+            p = path
+        ),
+    )?;
+    Ok(match output.trim() {
+        "d" => Some(PathKind::Dir),
+        "f" => Some(PathKind::File),
+        "o" => Some(PathKind::Other),
+        _ => None,
+    })
+}
+
+/// リモートのファイル、またはディレクトリ内の通常ファイルを要約します
+///
+/// 1回のssh接続で、各ファイルのサイズとSHA-256を取得します。
+/// `sha256sum` がない環境（macOSなど）では `shasum -a 256` を使用します。
+/// ファイルを指定した場合は空文字列をキーとし、存在しない場合は空の結果を返します。
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `path` - 要約するファイルまたはディレクトリ
+///
+/// # 戻り値
+/// ディレクトリからの相対パスごとの要約
+pub fn tree_digest(endpoint: &Endpoint, path: &str) -> Result<BTreeMap<String, FileDigest>> {
+    let script = format!(
+        "if command -v sha256sum >/dev/null 2>&1; then h='sha256sum'; else h='shasum -a 256'; fi; \
+         d() {{ printf '%s %s %s\\n' \"$(wc -c < \"$1\" | tr -d ' ')\" \"$($h < \"$1\" | cut -d' ' -f1)\" \"$2\"; }}; \
+         if [ -d {p} ]; then cd -- {p} && find . -type f | while IFS= read -r f; do d \"$f\" \"${{f#./}}\"; done; \
+         elif [ -f {p} ]; then d {p} ''; fi",
+        p = argv::quote_literal(path)
+    );
+    let output = run(endpoint, &script)?;
+
+    let mut tree = BTreeMap::new();
+    for line in output.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(size), Some(sha256), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let Ok(size) = size.parse() else { continue };
+        tree.insert(
+            path.to_string(),
+            FileDigest {
+                size,
+                sha256: sha256.to_string(),
+            },
+        );
+    }
+    Ok(tree)
+}
+
+/// 複数のリモートのファイルまたはディレクトリ内のファイルを一覧します
+///
+/// 1回のssh接続で、各パスについてディレクトリ内のファイルとシンボリックリンクを取得します。
+/// ディレクトリ以外の既存のパスは空文字列をキーとし、存在しないパスは空の結果を返します。
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `paths` - 一覧するパス
+///
+/// # 戻り値
+/// パスごとの、ディレクトリからの相対パスの集合
+pub fn list_files(endpoint: &Endpoint, paths: &[String]) -> Result<Vec<BTreeSet<String>>> {
+    let script: Vec<String> = paths
+        .iter()
+        .map(|path| {
+            format!(
+                "echo /; if [ -d {p} ]; then (cd -- {p} && find . \\( -type f -o -type l \\) -print); \
+                 elif [ -e {p} ] || [ -L {p} ]; then echo .; fi",
+                p = argv::quote_literal(path)
+            )
+        })
+        .collect();
+    let output = run(endpoint, &script.join("; "))?;
+
+    // 各パスの出力は "/" の行で始まる
+    let mut lists: Vec<BTreeSet<String>> = Vec::with_capacity(paths.len());
+    for line in output.lines() {
+        match (line, lists.last_mut()) {
+            ("/", _) => lists.push(BTreeSet::new()),
+            (".", Some(list)) => {
+                list.insert(String::new());
+            }
+            (line, Some(list)) => {
+                list.insert(line.strip_prefix("./").unwrap_or(line).to_string());
+            }
+            (_, None) => {}
+        }
+    }
+    lists.resize_with(paths.len(), BTreeSet::new);
+    Ok(lists)
+}
+
+/// リモートのファイルの名前を1回のssh接続でまとめて変更します
+///
+/// # 引数
+/// * `endpoint` - 接続先
+/// * `renames` - 変更前と変更後のパスの組
+pub fn rename_files(endpoint: &Endpoint, renames: &[(String, String)]) -> Result<()> {
+    let commands: Vec<String> = renames
+        .iter()
+        .map(|(from, to)| format!("mv -- {} {}", argv::quote_literal(from), argv::quote_literal(to)))
+        .collect();
+    run(endpoint, &commands.join(" && "))?;
+    Ok(())
+}
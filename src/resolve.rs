@@ -0,0 +1,580 @@
+// エイリアス解決機能
+//
+// このモジュールは、ホストエイリアスと "host:alias" 形式のパス指定を
+// 接続パラメータと実際のパスに解決します。
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::template::{self, TemplateContext};
+use std::collections::BTreeMap;
+
+/// SSH接続パラメータ
+///
+/// ホストエイリアスまたは直接指定された接続文字列から解決された、
+/// ssh/scp/rsyncの実行に必要な情報を保持します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Endpoint {
+    /// 設定上のホストエイリアス名（直接指定の場合はNone）
+    pub alias: Option<String>,
+    /// SSH接続文字列（例: "user@hostname"）
+    pub connection: String,
+    /// SSH接続ポート番号
+    pub port: u16,
+    /// SSH秘密鍵のパス（オプション）
+    pub key_path: Option<String>,
+    /// 転送時の帯域制限（KiB/s）
+    pub bwlimit: Option<u32>,
+    /// SSHの圧縮を使用するかどうか
+    pub compression: bool,
+    /// SSHの暗号方式
+    pub cipher: Option<String>,
+    /// sshportal専用のknown_hostsファイル（チルダ展開済み）
+    pub known_hosts: Option<String>,
+}
+
+impl Endpoint {
+    /// 接続文字列とポート番号だけを持つ接続先を作成します
+    ///
+    /// 設定を使わずに接続先を指定する場合に使用します。その他の項目は未設定になります。
+    ///
+    /// # 引数
+    /// * `connection` - SSH接続文字列（例: "user@hostname"）
+    /// * `port` - SSH接続ポート番号
+    pub fn new(connection: impl Into<String>, port: u16) -> Self {
+        Endpoint {
+            alias: None,
+            connection: connection.into(),
+            port,
+            key_path: None,
+            bwlimit: None,
+            compression: false,
+            cipher: None,
+            known_hosts: None,
+        }
+    }
+
+    /// SSH秘密鍵のパスを設定します
+    pub fn with_key_path(mut self, key_path: impl Into<String>) -> Self {
+        self.key_path = Some(key_path.into());
+        self
+    }
+
+    /// ホスト鍵の検証に使うknown_hostsファイルを設定します
+    pub fn with_known_hosts(mut self, known_hosts: impl Into<String>) -> Self {
+        self.known_hosts = Some(known_hosts.into());
+        self
+    }
+}
+
+/// リモートパスエイリアスの定義元
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasSource {
+    /// ホスト別のパス（host_paths）
+    Host,
+    /// 全ホスト共通のパス（remote_paths）
+    Global,
+    /// 旧形式のリモートパス（paths）
+    Legacy,
+}
+
+/// 解決済みのパス指定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// ローカルパス（チルダ展開済み）
+    Local(String),
+    /// リモートホスト上のパス
+    Remote {
+        /// 接続先
+        endpoint: Endpoint,
+        /// リモート側のパス
+        path: String,
+    },
+}
+
+impl Location {
+    /// ローカルパスかどうかを返します
+    pub fn is_local(&self) -> bool {
+        matches!(self, Location::Local(_))
+    }
+
+    /// リモートの場合は接続先を返します
+    pub fn endpoint(&self) -> Option<&Endpoint> {
+        match self {
+            Location::Local(_) => None,
+            Location::Remote { endpoint, .. } => Some(endpoint),
+        }
+    }
+
+    /// パス部分を返します
+    pub fn path(&self) -> &str {
+        match self {
+            Location::Local(path) => path,
+            Location::Remote { path, .. } => path,
+        }
+    }
+
+    /// scp/rsyncに渡す形式の文字列を返します
+    ///
+    /// ローカルの場合はパスそのもの、リモートの場合は "connection:path" を返します。
+    pub fn to_arg(&self) -> String {
+        match self {
+            Location::Local(path) => path.clone(),
+            Location::Remote { endpoint, path } => format!("{}:{}", endpoint.connection, path),
+        }
+    }
+
+    /// リモートパスをエスケープした形式の文字列を返します
+    ///
+    /// scpのリモートパスはリモート側で解釈されるため、glob文字を残してエスケープします。
+    pub fn to_escaped_arg(&self) -> String {
+        match self {
+            Location::Local(path) => path.clone(),
+            Location::Remote { endpoint, path } => {
+                format!("{}:{}", endpoint.connection, crate::argv::remote_escape(path))
+            }
+        }
+    }
+
+    /// scp向けに、リモートパスをglobを展開しないリテラルとしてクォートした引数を返します
+    ///
+    /// コピー先のように展開してはならないパスに使用します。先頭の `~` は展開されます。
+    pub fn to_literal_arg(&self) -> String {
+        match self {
+            Location::Local(path) => path.clone(),
+            Location::Remote { endpoint, path } if path.is_empty() => format!("{}:", endpoint.connection),
+            Location::Remote { endpoint, path } => {
+                format!("{}:{}", endpoint.connection, crate::argv::quote_literal(path))
+            }
+        }
+    }
+}
+
+/// ホスト指定を接続パラメータに解決します
+///
+/// 設定済みのホストエイリアスを優先し、見つからない場合は
+/// 直接のSSH接続文字列（user@hostname形式またはホスト名）として扱います。
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `host` - ホストエイリアスまたは接続文字列
+///
+/// # 戻り値
+/// 解決された接続パラメータ、またはエラー
+pub fn resolve_host(config: &Config, host: &str) -> Result<Endpoint> {
+    let known_hosts = config.known_hosts.as_deref().map(Config::expand_path).transpose()?;
+    if let Some(host_config) = config.hosts.get(host) {
+        return Ok(Endpoint {
+            alias: Some(host.to_string()),
+            connection: host_config.connection.clone(),
+            port: host_config.port,
+            key_path: host_config.key_path.clone(),
+            bwlimit: host_config.bwlimit,
+            compression: host_config.compression.unwrap_or(false),
+            cipher: host_config.cipher.clone(),
+            known_hosts,
+        });
+    }
+
+    // ホスト名が直接のSSH接続文字列の可能性（user@hostname形式）
+    if host.contains('@') || is_valid_hostname(host) {
+        return Ok(Endpoint {
+            alias: None,
+            connection: host.to_string(),
+            port: 22,
+            key_path: None,
+            bwlimit: None,
+            compression: false,
+            cipher: None,
+            known_hosts,
+        });
+    }
+
+    Err(Error::InvalidHost(host.to_string()))
+}
+
+/// パス指定文字列を解決します
+///
+/// "host:path"形式の文字列を解析し、ホスト名とパスに分離します。
+/// パスエイリアスとホストエイリアスの解決も行います。
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `spec` - 解析するパス指定文字列
+///
+/// # 戻り値
+/// 解決されたパス指定、またはエラー
+pub fn resolve_spec(config: &Config, spec: &str) -> Result<Location> {
+    // コロンが含まれる場合はリモートパスとして処理
+    if let Some((host, path)) = spec.split_once(':') {
+        let endpoint = resolve_host(config, host)?;
+        let path = match endpoint.alias {
+            Some(ref alias) => resolve_remote_alias(config, alias, path)?,
+            None => path.to_string(),
+        };
+        return Ok(Location::Remote { endpoint, path });
+    }
+
+    Ok(Location::Local(resolve_local_alias(config, spec)?))
+}
+
+/// パス指定の先頭に使用されているパスエイリアス名の候補を返します
+///
+/// `host:` を取り除いた後の最初の要素を返します。実際にエイリアスとして
+/// 定義されているかどうかは確認しません。
+///
+/// # 引数
+/// * `spec` - パス指定（エイリアスまたはhost:path）
+///
+/// # 戻り値
+/// エイリアス名の候補（リテラルのパスの場合はNone）
+pub fn alias_name(spec: &str) -> Option<&str> {
+    let path = spec.split_once(':').map(|(_, path)| path).unwrap_or(spec);
+    split_alias(path).map(|(name, _)| name)
+}
+
+/// 複数のコピー元指定を解決します
+///
+/// 各指定をエイリアス解決し、ローカルパスに含まれるglobはローカルで展開します。
+/// リモートパスのglobは展開せず、そのままリモート側に渡します。
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `specs` - コピー元の指定の一覧
+///
+/// # 戻り値
+/// 解決されたパス指定の一覧、またはエラー（globに一致するファイルがない場合を含む）
+pub fn resolve_sources(config: &Config, specs: &[String]) -> Result<Vec<Location>> {
+    let mut sources = Vec::new();
+    for spec in specs {
+        match resolve_spec(config, spec)? {
+            Location::Local(path) if has_glob(&path) => {
+                let pattern = glob::glob(&path)
+                    .map_err(|e| Error::InvalidPathSpec(format!("globパターン '{}' が不正です: {}", spec, e)))?;
+                let mut matches = Vec::new();
+                for entry in pattern {
+                    let entry = entry.map_err(|e| Error::Io(e.into()))?;
+                    matches.push(Location::Local(entry.to_string_lossy().to_string()));
+                }
+                if matches.is_empty() {
+                    return Err(Error::InvalidPathSpec(format!(
+                        "'{}' に一致するファイルがありません",
+                        spec
+                    )));
+                }
+                sources.extend(matches);
+            }
+            location => sources.push(location),
+        }
+    }
+    Ok(sources)
+}
+
+/// 文字列にglobの特殊文字が含まれるかどうかを返します
+pub fn has_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// ホストで有効なリモートパスエイリアスの一覧を返します
+///
+/// ホスト別パス、共通パス、旧形式のリモートパスの順に優先し、
+/// 上書きされたエイリアスは含みません。値はテンプレート展開前のものです。
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `host` - ホストエイリアス名
+///
+/// # 戻り値
+/// エイリアス名から (パス, 定義元) へのマップ
+pub fn remote_aliases(config: &Config, host: &str) -> BTreeMap<String, (String, AliasSource)> {
+    let mut aliases = BTreeMap::new();
+    if let Some(old_paths) = config.paths.as_ref() {
+        for (name, entry) in old_paths.iter().filter(|(_, e)| e.is_remote) {
+            aliases.insert(name.clone(), (entry.path.clone(), AliasSource::Legacy));
+        }
+    }
+    for (name, path) in &config.remote_paths {
+        aliases.insert(name.clone(), (path.clone(), AliasSource::Global));
+    }
+    for (name, path) in config.host_paths.get(host).into_iter().flatten() {
+        aliases.insert(name.clone(), (path.clone(), AliasSource::Host));
+    }
+    aliases
+}
+
+/// ホスト別リモートパスエイリアスを解決します
+///
+/// パスの最初の要素をエイリアスとして解決し、残りを連結します
+/// （例: `webroot/css` → `/var/www/html/css`）。
+/// エイリアスはホスト別パス、共通パス、旧形式のリモートパスの順に検索し、
+/// いずれにも該当しない場合は文字列をそのまま返します。
+fn resolve_remote_alias(config: &Config, host: &str, path: &str) -> Result<String> {
+    let Some((name, rest)) = split_alias(path) else {
+        return Ok(path.to_string());
+    };
+
+    if let Some(resolved) = config.host_paths.get(host).and_then(|paths| paths.get(name)) {
+        let resolved = expand_template(config, Some(host), resolved)?;
+        return Ok(join_subpath(&resolved, rest));
+    }
+
+    // 全ホスト共通のパス
+    if let Some(resolved) = config.remote_paths.get(name) {
+        let resolved = expand_template(config, Some(host), resolved)?;
+        return Ok(join_subpath(&resolved, rest));
+    }
+
+    // パス部分がパスエイリアスかチェック（旧形式との互換性）
+    if let Some(path_entry) = config.paths.as_ref().and_then(|paths| paths.get(name)) {
+        // リモートパスでない場合はエラー
+        if !path_entry.is_remote {
+            return Err(Error::InvalidPathSpec(format!(
+                "パス '{}' はリモートパスではありません",
+                name
+            )));
+        }
+        let resolved = expand_template(config, Some(host), &path_entry.path)?;
+        return Ok(join_subpath(&resolved, rest));
+    }
+
+    // 直接パスの場合
+    Ok(path.to_string())
+}
+
+/// ローカルパスエイリアスを解決します
+///
+/// パスの最初の要素をエイリアスとして解決し、残りを連結します
+/// （例: `downloads/report.pdf` → `~/Downloads/report.pdf`）。
+/// エイリアスはローカルパス、旧形式のパスの順に検索し、結果をチルダ展開して返します。
+fn resolve_local_alias(config: &Config, spec: &str) -> Result<String> {
+    let Some((name, rest)) = split_alias(spec) else {
+        return Config::expand_path(spec);
+    };
+
+    if let Some(resolved) = config.local_paths.get(name) {
+        let resolved = expand_template(config, None, resolved)?;
+        return Config::expand_path(&join_subpath(&resolved, rest));
+    }
+
+    // 旧形式との互換性
+    if let Some(path_entry) = config.paths.as_ref().and_then(|paths| paths.get(name)) {
+        // リモートパスの場合はホスト指定が必要
+        if path_entry.is_remote {
+            return Err(Error::InvalidPathSpec(format!(
+                "パス '{}' はリモートパスですが、ホストが指定されていません",
+                name
+            )));
+        }
+        let resolved = expand_template(config, None, &path_entry.path)?;
+        return Config::expand_path(&join_subpath(&resolved, rest));
+    }
+
+    // パスエイリアスでない場合は文字列をそのまま返す
+    Config::expand_path(spec)
+}
+
+/// エイリアスの値に含まれるテンプレート変数を展開します
+fn expand_template(config: &Config, host: Option<&str>, value: &str) -> Result<String> {
+    let context = TemplateContext {
+        host,
+        variables: &config.variables,
+    };
+    template::expand(value, &context)
+}
+
+/// パスをエイリアス名と残りのサブパスに分割します
+///
+/// `/`または`~`で始まるパスはリテラルとして扱い、Noneを返します。
+/// `./`で始めるとエイリアス名と衝突する名前もリテラルとして扱われます
+/// （最初の要素が`.`になるため）。
+///
+/// # 戻り値
+/// (エイリアス候補, 残りのサブパス) のタプル。サブパスがない場合はNone
+fn split_alias(path: &str) -> Option<(&str, Option<&str>)> {
+    if path.is_empty() || path.starts_with('/') || path.starts_with('~') {
+        return None;
+    }
+    match path.split_once('/') {
+        Some((name, rest)) => Some((name, Some(rest))),
+        None => Some((path, None)),
+    }
+}
+
+/// エイリアスの解決結果にサブパスを連結します
+///
+/// サブパスが空（`alias/`）の場合は末尾の`/`を保持します。
+fn join_subpath(base: &str, rest: Option<&str>) -> String {
+    match rest {
+        None => base.to_string(),
+        Some(rest) => format!("{}/{}", base.trim_end_matches('/'), rest),
+    }
+}
+
+/// ホスト名が有効かどうかをチェックします
+///
+/// 基本的なホスト名の形式をチェックします（RFC準拠ではない簡易版）
+///
+/// # 引数
+/// * `hostname` - チェックするホスト名
+///
+/// # 戻り値
+/// 有効なホスト名の場合はtrue
+pub fn is_valid_hostname(hostname: &str) -> bool {
+    if hostname.is_empty() || hostname.len() > 253 {
+        return false;
+    }
+
+    // 基本的なホスト名の規則をチェック
+    // - 英数字とハイフン、ピリオドのみ
+    // - ハイフンで始まらない、終わらない
+    // - 連続するピリオドがない
+    let chars: Vec<char> = hostname.chars().collect();
+
+    for (i, &ch) in chars.iter().enumerate() {
+        match ch {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => continue,
+            '-' => {
+                if i == 0 || i == chars.len() - 1 {
+                    return false;
+                }
+            }
+            '.' => {
+                if i == 0 || i == chars.len() - 1 {
+                    return false;
+                }
+                if i > 0 && chars[i - 1] == '.' {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+/// IPアドレス（IPv4）が有効かどうかをチェックします
+///
+/// # 引数
+/// * `ip` - チェックするIPアドレス文字列
+///
+/// # 戻り値
+/// 有効なIPv4アドレスの場合はtrue
+pub fn is_valid_ip_address(ip: &str) -> bool {
+    let parts: Vec<&str> = ip.split('.').collect();
+    if parts.len() != 4 {
+        return false;
+    }
+
+    for part in parts {
+        if part.parse::<u8>().is_err() {
+            return false;
+        }
+        if part.len() > 1 && part.starts_with('0') {
+            return false; // 先頭ゼロは無効
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Host, Path};
+    use std::collections::HashMap;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.hosts.insert("prod".to_string(), Host::new("u@prod", 22));
+        config.hosts.insert("dev".to_string(), Host::new("u@dev", 2222));
+        config
+    }
+
+    fn remote_path(config: &Config, spec: &str) -> String {
+        match resolve_spec(config, spec).unwrap() {
+            Location::Remote { path, .. } => path,
+            Location::Local(path) => panic!("ローカルパスに解決されました: {}", path),
+        }
+    }
+
+    #[test]
+    fn split_alias_treats_absolute_and_tilde_paths_as_literal() {
+        assert_eq!(split_alias("webroot"), Some(("webroot", None)));
+        assert_eq!(split_alias("webroot/css/a.css"), Some(("webroot", Some("css/a.css"))));
+        assert_eq!(split_alias("webroot/"), Some(("webroot", Some(""))));
+        assert_eq!(split_alias("./webroot"), Some((".", Some("webroot"))));
+        assert_eq!(split_alias("/var/www"), None);
+        assert_eq!(split_alias("~/www"), None);
+        assert_eq!(split_alias("~deploy"), None);
+        assert_eq!(split_alias(""), None);
+    }
+
+    #[test]
+    fn host_paths_take_precedence_over_remote_and_legacy_paths() {
+        let mut config = config();
+        config.paths = Some(HashMap::from([
+            ("webroot".to_string(), Path { path: "/legacy/www".to_string(), is_remote: true }),
+            ("logs".to_string(), Path { path: "/legacy/logs".to_string(), is_remote: true }),
+            ("data".to_string(), Path { path: "/legacy/data".to_string(), is_remote: true }),
+        ]));
+        config.remote_paths.insert("webroot".to_string(), "/global/www".to_string());
+        config.remote_paths.insert("logs".to_string(), "/global/logs/".to_string());
+        config
+            .host_paths
+            .insert("prod".to_string(), HashMap::from([("webroot".to_string(), "/prod/www".to_string())]));
+
+        assert_eq!(remote_path(&config, "prod:webroot/css"), "/prod/www/css");
+        assert_eq!(remote_path(&config, "dev:webroot/css"), "/global/www/css");
+        assert_eq!(remote_path(&config, "prod:logs/app.log"), "/global/logs/app.log");
+        assert_eq!(remote_path(&config, "prod:logs/"), "/global/logs/");
+        assert_eq!(remote_path(&config, "prod:data"), "/legacy/data");
+
+        let aliases = remote_aliases(&config, "prod");
+        assert_eq!(aliases["webroot"], ("/prod/www".to_string(), AliasSource::Host));
+        assert_eq!(aliases["logs"], ("/global/logs/".to_string(), AliasSource::Global));
+        assert_eq!(aliases["data"], ("/legacy/data".to_string(), AliasSource::Legacy));
+    }
+
+    #[test]
+    fn literal_paths_are_not_resolved_as_aliases() {
+        let mut config = config();
+        config.remote_paths.insert("webroot".to_string(), "/var/www".to_string());
+
+        assert_eq!(remote_path(&config, "prod:./webroot"), "./webroot");
+        assert_eq!(remote_path(&config, "prod:/webroot"), "/webroot");
+        assert_eq!(remote_path(&config, "prod:~/webroot"), "~/webroot");
+        assert_eq!(remote_path(&config, "prod:other/webroot"), "other/webroot");
+        // 設定にないホストではエイリアスを解決しない
+        assert_eq!(remote_path(&config, "u@other:webroot"), "webroot");
+    }
+
+    #[test]
+    fn legacy_local_path_requires_no_host() {
+        let mut config = config();
+        config.paths = Some(HashMap::from([
+            ("notes".to_string(), Path { path: "/tmp/notes".to_string(), is_remote: false }),
+            ("www".to_string(), Path { path: "/var/www".to_string(), is_remote: true }),
+        ]));
+
+        assert!(matches!(resolve_spec(&config, "notes/a.md").unwrap(), Location::Local(p) if p == "/tmp/notes/a.md"));
+        assert!(matches!(resolve_spec(&config, "www"), Err(Error::InvalidPathSpec(_))));
+        assert!(matches!(resolve_spec(&config, "prod:notes"), Err(Error::InvalidPathSpec(_))));
+    }
+
+    #[test]
+    fn alias_values_expand_template_variables() {
+        let mut config = config();
+        config.variables.insert("project".to_string(), "shop".to_string());
+        config.remote_paths.insert("deploy".to_string(), "/srv/{project}/{host}".to_string());
+        config.remote_paths.insert("pkg".to_string(), "/opt/{env:CARGO_PKG_NAME}".to_string());
+        config.remote_paths.insert("unknown".to_string(), "/srv/{nope}".to_string());
+        config.remote_paths.insert("unset".to_string(), "/srv/{env:SSHPORTAL_TEST_UNSET}".to_string());
+
+        assert_eq!(remote_path(&config, "prod:deploy/current"), "/srv/shop/prod/current");
+        assert_eq!(remote_path(&config, "dev:deploy"), "/srv/shop/dev");
+        assert_eq!(remote_path(&config, "prod:pkg"), "/opt/sshportal");
+        assert!(matches!(resolve_spec(&config, "prod:unknown"), Err(Error::Template(_))));
+        assert!(matches!(resolve_spec(&config, "prod:unset"), Err(Error::Template(_))));
+    }
+}
@@ -0,0 +1,140 @@
+// ホストの安全レベル機能
+//
+// このモジュールは、本番環境などの重要なホストへの誤った書き込みを防ぐため、
+// 保護されたホストへの書き込み前にホスト名の入力による確認を求め、
+// 読み取り専用のホストへの書き込みを拒否します。
+
+use crate::config::{Config, SafetyLevel};
+use crate::resolve::{self, Endpoint};
+use colored::*;
+use std::io::{self, IsTerminal, Write};
+
+/// `--yes` による確認の省略を許可する環境変数
+pub const ALLOW_YES_ENV: &str = "SSHPORTAL_ALLOW_YES";
+
+/// 接続先の安全レベルと設定上のホスト名を返します
+///
+/// エイリアスで指定された場合はそのホストの設定を、接続文字列で直接指定された場合は
+/// 同じ接続文字列を持つホストの設定を使用します（最も厳しいレベルを優先）。
+fn safety_of<'a>(config: &'a Config, endpoint: &'a Endpoint) -> (SafetyLevel, &'a str) {
+    if let Some(host) = endpoint.alias.as_ref().and_then(|alias| config.hosts.get_key_value(alias)) {
+        return (host.1.safety, host.0.as_str());
+    }
+    config
+        .hosts
+        .iter()
+        .filter(|(_, host)| host.connection == endpoint.connection)
+        .map(|(name, host)| (host.safety, name.as_str()))
+        .max_by_key(|(safety, _)| match safety {
+            SafetyLevel::Normal => 0,
+            SafetyLevel::Protected => 1,
+            SafetyLevel::ReadOnly => 2,
+        })
+        .unwrap_or((SafetyLevel::Normal, endpoint.connection.as_str()))
+}
+
+/// 接続先への書き込みが許可されているかを確認します
+///
+/// 読み取り専用のホストはエラーを返します。保護されたホストはホスト名の入力を求め、
+/// 一致しない場合はエラーを返します。`yes` が指定され、環境変数
+/// `SSHPORTAL_ALLOW_YES=1` が設定されている場合のみ確認を省略します。
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `endpoint` - 書き込み先の接続先
+/// * `yes` - 確認を省略するかどうか（`--yes`）
+///
+/// # 戻り値
+/// 書き込んでよい場合は保護されたホストかどうか、拒否された場合はエラー
+pub fn check_write(config: &Config, endpoint: &Endpoint, yes: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let (safety, name) = safety_of(config, endpoint);
+    match safety {
+        SafetyLevel::Normal => Ok(false),
+        SafetyLevel::ReadOnly => Err(format!("ホスト '{}' は読み取り専用のため、書き込みできません", name).into()),
+        SafetyLevel::Protected => {
+            confirm(name, &endpoint.connection, yes)?;
+            Ok(true)
+        }
+    }
+}
+
+/// ホスト指定（エイリアスまたは接続文字列）への書き込みが許可されているかを確認します
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `host` - 書き込み先のホスト
+/// * `yes` - 確認を省略するかどうか（`--yes`）
+///
+/// # 戻り値
+/// 書き込んでよい場合は保護されたホストかどうか、拒否された場合はエラー
+pub fn check_host(config: &Config, host: &str, yes: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    check_write(config, &resolve::resolve_host(config, host)?, yes)
+}
+
+/// 保護されたホストへの書き込みを確認します
+fn confirm(name: &str, connection: &str, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if yes {
+        if !yes_allowed() {
+            return Err(format!(
+                "保護されたホスト '{}' への書き込みを --yes で確認なしに行うには、環境変数 {}=1 が必要です",
+                name, ALLOW_YES_ENV
+            )
+            .into());
+        }
+        println!(
+            "{}: 保護されたホスト '{}' への書き込みを確認なしで実行します（{}）",
+            "WARN".yellow(),
+            name,
+            ALLOW_YES_ENV
+        );
+        return Ok(());
+    }
+
+    if !io::stdin().is_terminal() {
+        return Err(format!("保護されたホスト '{}' への書き込みには端末での確認が必要です", name).into());
+    }
+
+    println!(
+        "{}",
+        format!(" 保護されたホスト '{}' ({}) に書き込もうとしています ", name, connection)
+            .white()
+            .on_red()
+            .bold()
+    );
+    print!("続行するにはホスト名 '{}' を入力してください: ", name);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if answer.trim() != name {
+        return Err("ホスト名が一致しないため中止しました".into());
+    }
+    Ok(())
+}
+
+/// 環境変数で `--yes` が許可されているかどうか
+fn yes_allowed() -> bool {
+    std::env::var(ALLOW_YES_ENV).is_ok_and(|value| matches!(value.as_str(), "1" | "true" | "yes"))
+}
+
+/// 接続時に安全レベルを示すバナーを表示します
+///
+/// 保護されたホストは赤、読み取り専用のホストは黄色で表示します。
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `endpoint` - 接続先
+pub fn print_banner(config: &Config, endpoint: &Endpoint) {
+    let (safety, name) = safety_of(config, endpoint);
+    let banner = match safety {
+        SafetyLevel::Normal => return,
+        SafetyLevel::Protected => format!(" ⚠ PROTECTED: {} ({}) ", name, endpoint.connection)
+            .white()
+            .on_red()
+            .bold(),
+        SafetyLevel::ReadOnly => format!(" READ-ONLY: {} ({}) ", name, endpoint.connection)
+            .black()
+            .on_yellow()
+            .bold(),
+    };
+    println!("{}", banner);
+}
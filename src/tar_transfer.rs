@@ -0,0 +1,324 @@
+// tarストリーム転送機能
+//
+// このモジュールは、`tar c | ssh host tar x` のようにtarのストリームをパイプで
+// つないでファイルを転送します。小さなファイルが大量にあるディレクトリでは、
+// ファイルごとに往復が発生するscpより大幅に高速です。
+
+use crate::argv;
+use crate::resolve::Location;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Stdio};
+
+/// tarストリームの圧縮方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    /// 圧縮しない
+    None,
+    /// gzipで圧縮
+    Gzip,
+    /// zstdで圧縮
+    Zstd,
+}
+
+impl Compression {
+    /// 圧縮コマンドのargvを返します
+    fn compress_argv(&self) -> Option<Vec<String>> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(vec!["gzip".to_string(), "-c".to_string()]),
+            Compression::Zstd => Some(vec!["zstd".to_string(), "-q".to_string(), "-c".to_string()]),
+        }
+    }
+
+    /// 展開コマンドのargvを返します
+    fn decompress_argv(&self) -> Option<Vec<String>> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(vec!["gzip".to_string(), "-dc".to_string()]),
+            Compression::Zstd => Some(vec!["zstd".to_string(), "-q".to_string(), "-dc".to_string()]),
+        }
+    }
+}
+
+/// パイプラインの1段
+struct Stage {
+    /// エラー表示に使用する名前（例: "ローカルのtar"）
+    label: String,
+    /// 実行するargv
+    argv: Vec<String>,
+}
+
+/// tarストリームでコピーします
+///
+/// コピー元は同じ接続先（ローカルまたは同一ホスト）である必要があります。
+/// コピー先はディレクトリとして扱い、存在しない場合は作成します。
+/// パーミッションとシンボリックリンクは保持されます。
+/// 両端がリモートの場合は、ローカルを経由してストリームを中継します。
+///
+/// # 引数
+/// * `sources` - コピー元（1つ以上、同じ接続先）
+/// * `dst` - コピー先のディレクトリ
+/// * `compression` - 圧縮方式（両端がローカルの場合は使用しません）
+/// * `excludes` - tarの `--exclude` に渡す除外パターン
+///
+/// # 戻り値
+/// 成功時は()、いずれかの段が失敗した場合はその段と標準エラー出力を含むエラー
+pub fn copy(
+    sources: &[Location],
+    dst: &Location,
+    compression: Compression,
+    excludes: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let compression = if sources.iter().all(Location::is_local) && dst.is_local() {
+        Compression::None
+    } else {
+        compression
+    };
+
+    let mut stages = create_stages(sources, compression, excludes)?;
+    stages.extend(extract_stages(dst, compression)?);
+
+    // ローカルで実行するコマンドが存在することを事前に確認
+    for stage in &stages {
+        if !argv::is_available(&stage.argv[0]) {
+            return Err(format!("'{}' が見つかりません（{}）", stage.argv[0], stage.label).into());
+        }
+    }
+
+    run_pipeline(&stages)
+}
+
+/// アーカイブを作成する段を構築します
+fn create_stages(
+    sources: &[Location],
+    compression: Compression,
+    excludes: &[String],
+) -> Result<Vec<Stage>, Box<dyn std::error::Error>> {
+    let exclude_args: Vec<String> = excludes
+        .iter()
+        .map(|p| format!("--exclude={}", p.trim_start_matches('/').trim_end_matches('/')))
+        .collect();
+
+    match sources[0].endpoint() {
+        None => {
+            let mut tar = vec!["tar".to_string(), "-cf".to_string(), "-".to_string()];
+            tar.extend(exclude_args);
+            for src in sources {
+                let (parent, name) = split_local(src.path());
+                tar.extend(["-C".to_string(), parent, name]);
+            }
+            let mut stages = vec![Stage {
+                label: "ローカルのtar".to_string(),
+                argv: tar,
+            }];
+            if let Some(argv) = compression.compress_argv() {
+                stages.push(Stage {
+                    label: format!("ローカルの{}", argv[0]),
+                    argv,
+                });
+            }
+            Ok(stages)
+        }
+        Some(endpoint) => {
+            let splits: Vec<(String, String)> = sources.iter().map(|src| split_remote(src.path())).collect();
+            let excludes: Vec<String> = exclude_args.iter().map(|a| argv::shell_quote(a)).collect();
+            let mut command = if splits.iter().all(|(parent, _)| *parent == splits[0].0) {
+                // 同じディレクトリのコピー元はcdしてからまとめる（リモートのglobも展開される）
+                let names: Vec<String> = splits.iter().map(|(_, name)| argv::remote_escape(name)).collect();
+                format!(
+                    "cd -- {} && tar -cf - {} -- {}",
+                    argv::remote_escape(&splits[0].0),
+                    excludes.join(" "),
+                    names.join(" ")
+                )
+            } else {
+                if sources.iter().any(|src| crate::resolve::has_glob(src.path())) {
+                    return Err("異なるディレクトリにあるリモートのglobはtarバックエンドでまとめて転送できません".into());
+                }
+                let entries: Vec<String> = splits
+                    .iter()
+                    .map(|(parent, name)| format!("-C {} {}", argv::remote_escape(parent), argv::remote_escape(name)))
+                    .collect();
+                format!("tar -cf - {} {}", excludes.join(" "), entries.join(" "))
+            };
+            if let Some(argv) = compression.compress_argv() {
+                command = format!("{}{}", require_program(&argv[0]), pipe_status(&command, &argv.join(" ")));
+            }
+            Ok(vec![Stage {
+                label: format!("リモート（{}）のtar", endpoint.connection),
+                argv: argv::ssh_exec_argv(endpoint, &command),
+            }])
+        }
+    }
+}
+
+/// アーカイブを展開する段を構築します
+fn extract_stages(dst: &Location, compression: Compression) -> Result<Vec<Stage>, Box<dyn std::error::Error>> {
+    match dst {
+        Location::Local(path) => {
+            let dir = Path::new(path);
+            if dir.exists() && !dir.is_dir() {
+                return Err(format!("tarバックエンドのコピー先 '{}' はディレクトリである必要があります", path).into());
+            }
+            fs::create_dir_all(dir)?;
+
+            let mut stages = Vec::new();
+            if let Some(argv) = compression.decompress_argv() {
+                stages.push(Stage {
+                    label: format!("ローカルの{}", argv[0]),
+                    argv,
+                });
+            }
+            stages.push(Stage {
+                label: "ローカルのtar".to_string(),
+                argv: vec![
+                    "tar".to_string(),
+                    "-xpf".to_string(),
+                    "-".to_string(),
+                    "-C".to_string(),
+                    path.clone(),
+                ],
+            });
+            Ok(stages)
+        }
+        Location::Remote { endpoint, path } => {
+            let dir = argv::quote_literal(if path.is_empty() { "." } else { path });
+            let extract = match compression.decompress_argv() {
+                Some(argv) => format!("{}{}", require_program(&argv[0]), pipe_status(&argv.join(" "), "tar -xpf -")),
+                None => "tar -xpf -".to_string(),
+            };
+            Ok(vec![Stage {
+                label: format!("リモート（{}）のtar", endpoint.connection),
+                argv: argv::ssh_exec_argv(endpoint, &format!("mkdir -p -- {d} && cd -- {d} && {e}", d = dir, e = extract)),
+            }])
+        }
+    }
+}
+
+/// 段をパイプでつないで実行し、全ての段の終了を待ちます
+fn run_pipeline(stages: &[Stage]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut children: Vec<Child> = Vec::with_capacity(stages.len());
+    for (i, stage) in stages.iter().enumerate() {
+        let stdin = match children.last_mut().and_then(|c| c.stdout.take()) {
+            Some(stdout) => Stdio::from(stdout),
+            None => Stdio::null(),
+        };
+        let stdout = if i + 1 < stages.len() { Stdio::piped() } else { Stdio::null() };
+        children.push(
+            argv::command(&stage.argv)
+                .stdin(stdin)
+                .stdout(stdout)
+                .stderr(Stdio::piped())
+                .spawn()?,
+        );
+    }
+
+    // 後段が標準エラー出力のパイプを埋めて停止しないよう、各段の標準エラー出力を別スレッドで読み続ける
+    let readers: Vec<_> = children
+        .iter_mut()
+        .map(|child| {
+            let pipe = child.stderr.take();
+            std::thread::spawn(move || {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = pipe {
+                    let _ = pipe.read_to_string(&mut stderr);
+                }
+                stderr
+            })
+        })
+        .collect();
+
+    let mut failures = Vec::new();
+    for ((stage, mut child), reader) in stages.iter().zip(children).zip(readers) {
+        let status = child.wait()?;
+        let stderr = reader.join().unwrap_or_default();
+        if !status.success() || !stderr.trim().is_empty() {
+            let message = match stderr.trim() {
+                "" => format!("終了コード {}", status.code().unwrap_or(-1)),
+                stderr => stderr.to_string(),
+            };
+            if !status.success() {
+                failures.push(format!("{}: {}", stage.label, message));
+            } else {
+                eprintln!("{}: {}", stage.label, message);
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("tarストリーム転送に失敗しました\n  {}", failures.join("\n  ")).into())
+    }
+}
+
+/// リモートにプログラムがない場合にエラー終了するシェル断片を返します
+fn require_program(program: &str) -> String {
+    format!(
+        "command -v {p} >/dev/null 2>&1 || {{ echo '{p} が見つかりません' >&2; exit 127; }}; ",
+        p = program
+    )
+}
+
+/// 両方の段の終了コードを反映するパイプラインのシェル断片を返します
+///
+/// POSIXシェルのパイプラインは最後のコマンドの終了コードしか返さないため、
+/// 各段の終了コードをファイルディスクリプタ経由で受け渡し、いずれかが0以外の場合は
+/// その終了コードで終了します（両方が失敗した場合は後に終了した段の終了コード）。
+fn pipe_status(first: &str, second: &str) -> String {
+    format!(
+        "{{ {{ {{ {}; echo $? >&3; }} | {{ {}; echo $? >&3; }} >&4; }} 3>&1 | \
+         {{ s=0; while read c; do [ \"$c\" -eq 0 ] || s=$c; done; exit $s; }}; }} 4>&1",
+        first, second
+    )
+}
+
+/// ローカルパスを親ディレクトリと名前に分割します
+///
+/// `.` や `..` のように名前を持たないパスはディレクトリの内容を転送します。
+fn split_local(path: &str) -> (String, String) {
+    let path = Path::new(path);
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            (parent.to_string_lossy().to_string(), name.to_string_lossy().to_string())
+        }
+        _ => (path.to_string_lossy().to_string(), ".".to_string()),
+    }
+}
+
+/// リモートパスを親ディレクトリと名前に分割します
+fn split_remote(path: &str) -> (String, String) {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rsplit_once('/') {
+        Some(("", name)) => ("/".to_string(), name.to_string()),
+        Some((parent, name)) => (parent.to_string(), name.to_string()),
+        None if trimmed.is_empty() || trimmed == "~" => (if path.is_empty() { "." } else { path }.to_string(), ".".to_string()),
+        None => (".".to_string(), trimmed.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn run(first: &str, second: &str) -> (Option<i32>, String) {
+        let output = Command::new("sh").arg("-c").arg(pipe_status(first, second)).output().unwrap();
+        (output.status.code(), String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    #[test]
+    fn pipe_status_passes_output_through() {
+        assert_eq!(run("printf 'a\\nb\\n'", "tr a-z A-Z"), (Some(0), "A\nB\n".to_string()));
+    }
+
+    #[test]
+    fn pipe_status_fails_when_either_stage_fails() {
+        assert_eq!(run("sh -c 'exit 3'", "cat").0, Some(3));
+        assert_eq!(run("echo hi", "cat >/dev/null; sh -c 'exit 4'").0, Some(4));
+        assert_ne!(run("sh -c 'exit 3'", "sh -c 'exit 4'").0, Some(0));
+    }
+}
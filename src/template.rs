@@ -0,0 +1,173 @@
+// パステンプレート機能
+//
+// このモジュールは、パスエイリアスの値に含まれる `{date}` や `{env:VAR}` などの
+// 変数を展開します。未定義の変数はそのまま残さずエラーにします。
+// 変数名として解釈できない波括弧（`{a,b}` や対応しない `{`・`}`）はそのまま残します。
+
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::process::Command;
+
+/// 組み込み変数の名前一覧
+pub const BUILTIN_VARIABLES: [&str; 7] = ["date", "time", "host", "user", "cwd_basename", "git_branch", "env"];
+
+/// テンプレート展開のコンテキスト
+pub struct TemplateContext<'a> {
+    /// 転送先のホストエイリアス（ローカルパスの場合はNone）
+    pub host: Option<&'a str>,
+    /// 設定で定義されたユーザー変数
+    pub variables: &'a HashMap<String, String>,
+}
+
+/// テンプレート文字列を展開します
+///
+/// `{name}` を変数の値に置き換えます。`{{` と `}}` はそれぞれ `{` と `}` になります。
+/// 波括弧の中が変数名として解釈できない場合（`{a,b}` など）や対応する括弧がない場合は、
+/// 変数ではなく文字としてそのまま残します。
+///
+/// 組み込み変数:
+/// * `{date}` - 今日の日付（YYYY-MM-DD）
+/// * `{time}` - 現在時刻（HHMMSS）
+/// * `{host}` - ホストエイリアス（ローカルパスではローカルのホスト名）
+/// * `{user}` - ローカルのユーザー名
+/// * `{env:VAR}` - 環境変数VARの値
+/// * `{cwd_basename}` - カレントディレクトリ名
+/// * `{git_branch}` - カレントディレクトリのgitブランチ名
+///
+/// # 引数
+/// * `template` - 展開するテンプレート文字列
+/// * `context` - 展開のコンテキスト
+///
+/// # 戻り値
+/// 展開された文字列、または未定義の変数の場合はエラー
+pub fn expand(template: &str, context: &TemplateContext) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(i) = rest.find(['{', '}']) {
+        output.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            output.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+
+        // 次の `}` までが変数名として解釈できる場合だけ変数として展開する
+        let reference = tail
+            .strip_prefix('{')
+            .and_then(|inner| inner.find('}').map(|end| &inner[..end]))
+            .filter(|name| is_reference(name));
+        match reference {
+            Some(name) => {
+                output.push_str(&lookup(name, context)?);
+                rest = &tail[name.len() + 2..];
+            }
+            None => {
+                output.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// ユーザー変数の名前として使用できるかを返します
+///
+/// 英数字と `_`・`-`・`.` のみからなる名前を変数名とします。
+pub fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+}
+
+/// 波括弧の中身が変数の参照（変数名または `env:VAR`）かどうかを返します
+fn is_reference(name: &str) -> bool {
+    is_variable_name(name.strip_prefix("env:").unwrap_or(name))
+}
+
+/// 変数の値を取得します
+///
+/// ユーザー変数を組み込み変数より優先します。
+fn lookup(name: &str, context: &TemplateContext) -> Result<String> {
+    if let Some(value) = context.variables.get(name) {
+        return Ok(value.clone());
+    }
+
+    let now = chrono::Local::now();
+    let value = match name {
+        "date" => Some(now.format("%Y-%m-%d").to_string()),
+        "time" => Some(now.format("%H%M%S").to_string()),
+        "host" => match context.host {
+            Some(host) => Some(host.to_string()),
+            None => command_output("hostname", &[]),
+        },
+        "user" => std::env::var("USER").ok(),
+        "cwd_basename" => std::env::current_dir()
+            .ok()
+            .and_then(|dir| dir.file_name().map(|n| n.to_string_lossy().to_string())),
+        "git_branch" => command_output("git", &["rev-parse", "--abbrev-ref", "HEAD"]),
+        _ => match name.strip_prefix("env:") {
+            Some(var) => Some(std::env::var(var).map_err(|_| {
+                Error::Template(format!("環境変数 '{}' が設定されていません", var))
+            })?),
+            None => return Err(Error::Template(format!("未定義の変数 '{{{}}}' です", name))),
+        },
+    };
+
+    value.ok_or_else(|| Error::Template(format!("変数 '{{{}}}' の値を取得できません", name)))
+}
+
+/// コマンドを実行し、成功した場合は標準出力の1行目を返します
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|line| line.trim().to_string()).filter(|l| !l.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_with(template: &str, host: Option<&str>, variables: &[(&str, &str)]) -> Result<String> {
+        let variables = variables.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        expand(template, &TemplateContext { host, variables: &variables })
+    }
+
+    #[test]
+    fn expands_user_and_builtin_variables() {
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        assert_eq!(expand_with("/srv/{project}/{host}", Some("prod"), &[("project", "shop")]).unwrap(), "/srv/shop/prod");
+        assert!(expand_with("/backup/{date}", None, &[]).unwrap().starts_with(&format!("/backup/{}", &date[..8])));
+        // ユーザー変数は組み込み変数より優先する
+        assert_eq!(expand_with("{host}", Some("prod"), &[("host", "web")]).unwrap(), "web");
+        assert_eq!(expand_with("/opt/{env:CARGO_PKG_NAME}", None, &[]).unwrap(), "/opt/sshportal");
+    }
+
+    #[test]
+    fn unknown_variables_are_errors() {
+        assert!(matches!(expand_with("/srv/{nope}", None, &[]), Err(Error::Template(_))));
+        assert!(matches!(expand_with("{env:SSHPORTAL_TEST_UNSET}", None, &[]), Err(Error::Template(_))));
+    }
+
+    #[test]
+    fn doubled_braces_are_escapes() {
+        assert_eq!(expand_with("{{host}}", Some("prod"), &[]).unwrap(), "{host}");
+        assert_eq!(expand_with("a{{b", None, &[]).unwrap(), "a{b");
+        assert_eq!(expand_with("a}}b", None, &[]).unwrap(), "a}b");
+        assert_eq!(expand_with("{{{host}}}", Some("prod"), &[]).unwrap(), "{prod}");
+    }
+
+    #[test]
+    fn braces_that_are_not_references_stay_literal() {
+        assert_eq!(expand_with("/srv/{a,b}", None, &[]).unwrap(), "/srv/{a,b}");
+        assert_eq!(expand_with("/srv/{", None, &[]).unwrap(), "/srv/{");
+        assert_eq!(expand_with("/srv/}", None, &[]).unwrap(), "/srv/}");
+        assert_eq!(expand_with("{}", None, &[]).unwrap(), "{}");
+        assert_eq!(expand_with("{ host }", Some("prod"), &[]).unwrap(), "{ host }");
+        assert_eq!(expand_with("{open/{host}", Some("prod"), &[]).unwrap(), "{open/prod");
+    }
+}
@@ -0,0 +1,1105 @@
+// ファイル転送機能
+//
+// このモジュールは、パスエイリアスを解決したうえでscp/rsync/ネイティブSFTPの
+// いずれかのバックエンドでファイルを転送し、進捗と結果の概要を表示します。
+
+use crate::argv;
+use crate::audit::{self, AuditRecord};
+use crate::browser;
+use crate::clobber::{self, Resolution};
+use crate::config::Config;
+use crate::error::Error;
+use crate::filter::{TransferFilter, IGNORE_FILE};
+use crate::progress::{self, LocalScan, ProgressBar};
+use crate::remote;
+use crate::resolve::{self, Location};
+use crate::safety;
+use crate::tar_transfer::{self, Compression};
+use crate::verify;
+use clap::ValueEnum;
+use colored::*;
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+/// ファイル転送に使用するバックエンド
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// ローカルとリモートの両方でrsyncが使用できる場合はrsync、それ以外はscp
+    Auto,
+    /// 外部のscpコマンド（sshportalのプログレスバーは表示せず、scp自身のファイルごとの進捗を表示）
+    Scp,
+    /// 外部のrsyncコマンド
+    Rsync,
+    /// russh（純粋なRustのSSH実装）によるネイティブSFTP転送（`native`フィーチャーが必要）
+    Native,
+    /// `tar c | ssh host tar x` によるストリーム転送
+    Tar,
+}
+
+/// ファイル転送のオプション
+/// 
+/// copy/pull/push の各サブコマンドで共通のコマンドライン引数としても使用します。
+#[derive(Debug, Clone, clap::Args)]
+pub struct CopyOptions {
+    /// 使用するバックエンド
+    #[arg(long, value_enum, default_value = "auto", help = "転送バックエンド")]
+    pub backend: Backend,
+    /// 途中までのファイルから転送を再開する（rsyncとネイティブ転送のみ、検証を伴う）
+    #[arg(long, help = "途中までのファイルから転送を再開（rsync/nativeのみ、--verifyを含む）")]
+    pub resume: bool,
+    /// 帯域制限（KiB/s、0で無制限）。ホストの設定より優先する
+    #[arg(long, value_name = "KIB", help = "帯域制限（KiB/s、0で無制限、scp/rsyncのみ）")]
+    pub bwlimit: Option<u32>,
+    /// SSHの圧縮を有効にする。ホストの設定より優先する
+    #[arg(short = 'C', long, conflicts_with = "no_ssh_compression", help = "SSHの圧縮を有効にする")]
+    pub ssh_compression: bool,
+    /// SSHの圧縮を無効にする。ホストの設定より優先する
+    #[arg(long, help = "SSHの圧縮を無効にする")]
+    pub no_ssh_compression: bool,
+    /// SSHの暗号方式。ホストの設定より優先する
+    #[arg(long, value_name = "CIPHER", help = "SSHの暗号方式（例: aes128-gcm@openssh.com）")]
+    pub cipher: Option<String>,
+    /// 実効設定と実行するコマンドを表示して終了する
+    #[arg(long, help = "帯域制限・圧縮・暗号方式の実効値とコマンドを表示して終了")]
+    pub print: bool,
+    /// 既存のファイルを上書きしない
+    #[arg(short = 'n', long, conflicts_with_all = ["backup", "force"], help = "既存のファイルを上書きせずにスキップ")]
+    pub no_clobber: bool,
+    /// 上書きされるファイルをタイムスタンプ付きの名前に退避する
+    #[arg(long, conflicts_with = "force", help = "上書きされるファイルを 名前.YYYYMMDD-HHMMSS に退避")]
+    pub backup: bool,
+    /// 既存のファイルを確認せずに上書きする
+    #[arg(short = 'f', long, help = "既存のファイルを確認せずに上書き")]
+    pub force: bool,
+    /// 保護されたホストへの書き込みの確認を省略する（環境変数での許可が必要）
+    #[arg(short = 'y', long, help = "保護されたホストへの書き込みの確認を省略（SSHPORTAL_ALLOW_YES=1が必要）")]
+    pub yes: bool,
+    /// `host:` で終わる引数をリモートディレクトリブラウザで選択する
+    #[arg(short = 'b', long, help = "host: で終わる引数をリモートディレクトリブラウザで選択（端末が必要）")]
+    pub browse: bool,
+    /// キューに登録してバックグラウンドで実行する
+    #[arg(long, conflicts_with_all = ["print", "browse"], help = "キューに登録してバックグラウンドで実行（jobsで確認）")]
+    pub background: bool,
+    /// 失敗した場合に再試行する回数
+    #[arg(long, default_value_t = 0, value_name = "N", help = "失敗時に再試行する回数（間隔は1秒から倍増）")]
+    pub retries: u32,
+    /// 転送後にチェックサムを検証する
+    #[arg(long, help = "転送後にSHA-256でファイルごとに検証")]
+    pub verify: bool,
+    /// tarストリーム転送の圧縮方式
+    #[arg(long, value_enum, default_value = "none", help = "tarバックエンドの圧縮方式")]
+    pub compress: Compression,
+    /// ファイルごとの一覧を表示する
+    #[arg(short = 'v', long, help = "ファイルごとの一覧を表示")]
+    pub verbose: bool,
+    /// 転送から除外するパターン
+    #[arg(long, value_name = "PATTERN", help = "除外するパターン（複数指定可）")]
+    pub exclude: Vec<String>,
+    /// 除外パターンに一致しても転送するパターン
+    #[arg(long, value_name = "PATTERN", help = "除外パターンより優先して転送するパターン（複数指定可）")]
+    pub include: Vec<String>,
+}
+
+impl CopyOptions {
+    /// 転送後に検証するかどうかを返します
+    ///
+    /// 再開したファイルは途中までの内容が正しいとは限らないため、`--resume` は検証を伴います。
+    fn verify_enabled(&self) -> bool {
+        self.verify || self.resume
+    }
+
+    /// コマンドラインで指定された接続設定をリモートの接続先に適用します
+    fn apply_overrides(&self, location: &mut Location) {
+        let Location::Remote { endpoint, .. } = location else { return };
+        if let Some(limit) = self.bwlimit {
+            endpoint.bwlimit = (limit > 0).then_some(limit);
+        }
+        if self.ssh_compression {
+            endpoint.compression = true;
+        }
+        if self.no_ssh_compression {
+            endpoint.compression = false;
+        }
+        if let Some(ref cipher) = self.cipher {
+            endpoint.cipher = Some(cipher.clone());
+        }
+    }
+}
+
+/// 転送結果の集計
+struct TransferSummary {
+    /// 転送が成功したかどうか
+    success: bool,
+    /// 転送したファイル数（不明な場合はNone）
+    files: Option<u64>,
+    /// 転送したバイト数（不明な場合はNone）
+    bytes: Option<u64>,
+    /// 経過時間
+    elapsed: Duration,
+}
+
+/// ファイルをコピーします
+///
+/// パスエイリアスとホストエイリアスを解決し、選択されたバックエンドで転送します。
+/// ローカル⇔リモート、リモート⇔ローカル、リモート⇔リモートのコピーに対応します
+/// （リモート⇔リモートはscpのみ）。
+///
+/// バックエンドの既定値 `auto` では、プログレスバーを表示できるrsyncをローカルとリモートの
+/// 両方で使用できる場合はrsyncで、それ以外はscpで転送します。rsyncで転送する場合も
+/// コピー先の配置はscpと同じになるように調整します。
+///
+/// コピー元は`cp`と同様に複数指定でき、ローカルのglobはローカルで展開されます。
+/// コピー元がローカルと複数のホストにまたがる場合は、コピー元ごとに分けて転送します。
+///
+/// `--exclude`/`--include`、エイリアスのデフォルトの除外パターン、コピー元の
+/// `.sshportalignore` のいずれかがある場合、scpは除外に対応しないためrsync
+/// （使用できない場合はtarストリーム）で転送します。切り替えた場合もコピー先の配置は
+/// scpと同じになるように調整し、tarで再現できない配置の場合はエラーを返します。
+///
+/// # 引数
+/// * `sources` - コピー元の指定（パスエイリアスまたは実際のパス、1つ以上）
+/// * `dst` - コピー先の指定（パスエイリアスまたは実際のパス）
+/// * `options` - 転送オプション
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn copy_files(sources: &[String], dst: &str, options: &CopyOptions) -> Result<(), Box<dyn std::error::Error>> {
+    // 現在の設定を読み込み
+    let config = Config::load()?;
+
+    // --browse の場合、"host:" で終わる引数はリモートディレクトリブラウザで選択
+    let (sources, dst) = if options.browse {
+        (
+            browse_placeholders(&config, sources)?,
+            browse_placeholders(&config, &[dst.to_string()])?.remove(0),
+        )
+    } else {
+        (sources.to_vec(), dst.to_string())
+    };
+    let (sources, dst) = (sources.as_slice(), dst.as_str());
+
+    // 名前解決や書き込み先の確認で拒否された場合も含めて監査ログに記録する
+    let requested = options.backend.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+    let mut record = AuditRecord::new("copy", sources, dst, &requested);
+    let result = copy_resolved(&config, sources, dst, options, &mut record);
+    // --print では転送しないため記録しない
+    if !options.print {
+        audit::record(&config, &record.result(&result));
+    }
+    result
+}
+
+/// コピー元とコピー先を解決してコピーし、監査ログの記録に結果を設定します
+///
+/// # 引数
+/// * `config` - 現在の設定
+/// * `sources` - コピー元の指定
+/// * `dst` - コピー先の指定
+/// * `options` - 転送オプション
+/// * `record` - 監査ログの記録（解決後のパス、バックエンド、転送量、チェックサムを設定）
+fn copy_resolved(
+    config: &Config,
+    sources: &[String],
+    dst: &str,
+    options: &CopyOptions,
+    record: &mut AuditRecord,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // コピー元とコピー先のエイリアスを解決し、コマンドラインの接続設定で上書き
+    let mut src_locations = resolve::resolve_sources(config, sources)?;
+    let mut dst_location = resolve::resolve_spec(config, dst)?;
+    for location in src_locations.iter_mut().chain(std::iter::once(&mut dst_location)) {
+        options.apply_overrides(location);
+    }
+    record.set_locations(&src_locations, &dst_location);
+
+    // 複数のコピー元をまとめる場合、ローカルのコピー先はディレクトリでなければならない
+    if let Location::Local(ref path) = dst_location {
+        let path = Path::new(path);
+        if src_locations.len() > 1 && path.exists() && !path.is_dir() {
+            return Err(format!("複数のコピー元を指定した場合、コピー先 '{}' はディレクトリである必要があります", dst).into());
+        }
+    }
+
+    if options.resume && !matches!(options.backend, Backend::Auto | Backend::Native | Backend::Rsync) {
+        println!("{}: --resume は rsync と native バックエンドでのみ有効です", "WARN".yellow());
+    }
+
+    let filter = build_filter(config, sources, &src_locations, options)?;
+    if options.verbose && !filter.is_empty() {
+        println!("{}: 除外: {:?} 包含: {:?}", "INFO".blue(), filter.exclude, filter.include);
+    }
+
+    if options.print {
+        return print_effective(config, &src_locations, &dst_location, &filter, options);
+    }
+
+    // 読み取り専用のホストへの書き込みを拒否し、保護されたホストでは確認を求める
+    if let Some(endpoint) = dst_location.endpoint() {
+        safety::check_write(config, endpoint, options.yes)?;
+    }
+
+    println!("{}: {} から {} にコピー中...", "INFO".blue(), sources.join(", "), dst);
+
+    let mut total = TransferSummary {
+        success: true,
+        files: Some(0),
+        bytes: Some(0),
+        elapsed: Duration::ZERO,
+    };
+    let mut backends = Vec::new();
+    let mut checksums = Vec::new();
+    let result = transfer_groups(
+        &src_locations,
+        &dst_location,
+        &filter,
+        options,
+        &mut total,
+        &mut backends,
+        &mut checksums,
+    );
+
+    // 転送の結果を監査ログの記録に設定（チェックサムは検証で求めたものを使用し、改めて計算しない）
+    let backend_names: Vec<String> = backends
+        .iter()
+        .filter_map(|b: &Backend| b.to_possible_value().map(|v| v.get_name().to_string()))
+        .collect();
+    if !backend_names.is_empty() {
+        record.backend = backend_names.join(",");
+    }
+    record.bytes = total.bytes;
+    record.checksum = audit::manifest_checksum(&checksums);
+
+    result
+}
+
+/// コピー元を接続先ごとにまとめて転送し、結果を表示します
+///
+/// # 引数
+/// * `src_locations` - 解決済みのコピー元
+/// * `dst_location` - 解決済みのコピー先
+/// * `filter` - 除外・包含パターン
+/// * `options` - 転送オプション
+/// * `total` - 転送量の合計（転送中に更新）
+/// * `backends` - 実際に使用したバックエンド（転送中に追加）
+/// * `checksums` - 検証で一致したファイルのパスとSHA-256（検証時に追加）
+///
+/// # 戻り値
+/// 成功時は()、転送または検証に失敗した場合はエラーを返します。
+fn transfer_groups(
+    src_locations: &[Location],
+    dst_location: &Location,
+    filter: &TransferFilter,
+    options: &CopyOptions,
+    total: &mut TransferSummary,
+    backends: &mut Vec<Backend>,
+    checksums: &mut Vec<(String, String)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut verified = 0;
+    let mut mismatched = Vec::new();
+
+    // 同じ接続先（ローカルまたは同一ホスト）のコピー元ごとに転送
+    for group in src_locations.chunk_by(|a, b| a.endpoint() == b.endpoint()) {
+        let requested = match options.backend {
+            Backend::Auto => auto_backend(group, dst_location, true)?,
+            backend => backend,
+        };
+        if options.resume && requested == Backend::Scp && options.backend == Backend::Auto {
+            println!("{}: rsync が使用できないため scp で転送します（--resume は無効）", "WARN".yellow());
+        }
+        let mut backend = filtered_backend(requested, filter, group, dst_location)?;
+        let group = &scp_layout(group, dst_location, options.backend, backend)?;
+
+        // コピー先の配置は転送前の状態で決まるため、検証と上書きの確認の対象は転送前に求める
+        // （nativeは転送中に検証する）
+        let verify_group = options.verify_enabled() && backend != Backend::Native;
+        let mut pairs = if verify_group || !options.force {
+            for src in group.iter().filter(|src| !src.is_local() && resolve::has_glob(src.path())) {
+                println!(
+                    "{}: リモートのglob '{}' は検証・上書きの確認の対象外です",
+                    "WARN".yellow(),
+                    src.to_arg()
+                );
+            }
+            verify::plan(group, dst_location, backend)?
+        } else {
+            Vec::new()
+        };
+
+        // 上書きされるファイルを確認し、扱いを決める
+        let mut group = group.to_vec();
+        let mut group_options = options.clone();
+        if !options.force {
+            let skip = resolve_conflicts(&mut pairs, filter, options)?;
+            if skip {
+                group_options.no_clobber = true;
+                // 既存の単一ファイルのコピー元は転送しない
+                for pair in pairs.iter().filter(|pair| pair.skipped.contains("")) {
+                    println!("{}: 既存のためスキップします: {}", "INFO".blue(), pair.dst.to_arg());
+                    group.retain(|src| *src != pair.src);
+                }
+                // ディレクトリ内の既存のファイルをスキップできるのはrsyncのみ
+                let in_directory = pairs.iter().any(|pair| !pair.skipped.is_empty() && !pair.skipped.contains(""));
+                if in_directory && backend != Backend::Rsync {
+                    let remote_to_remote = !dst_location.is_local() && group.iter().any(|s| !s.is_local());
+                    if remote_to_remote || !argv::is_available("rsync") {
+                        return Err(Error::Unsupported(
+                            "ディレクトリ内の既存のファイルをスキップするには rsync が必要です（リモート間のコピーでは使用できません）"
+                                .to_string(),
+                        )
+                        .into());
+                    }
+                    println!("{}: 既存のファイルをスキップするため、rsync で転送します", "INFO".blue());
+                    group = scp_layout(&group, dst_location, backend, Backend::Rsync)?;
+                    backend = Backend::Rsync;
+                }
+            }
+        }
+        if group.is_empty() {
+            continue;
+        }
+
+        if !backends.contains(&backend) {
+            backends.push(backend);
+        }
+        if matches!(backend, Backend::Native | Backend::Tar)
+            && group.iter().chain(std::iter::once(dst_location)).any(|l| l.endpoint().is_some_and(|e| e.bwlimit.is_some()))
+        {
+            println!("{}: 帯域制限は scp と rsync バックエンドでのみ有効です", "WARN".yellow());
+        }
+
+        // ローカルのコピー元は事前にスキャンして合計サイズを求める
+        // （除外パターンがある場合やスキップするファイルがある場合は実際の転送量と一致しないためスキャンしない）
+        let scan = if filter.is_empty() && !group_options.no_clobber { scan_sources(&group) } else { None };
+
+        let options = &group_options;
+        let summary = with_retries(options.retries, || match backend {
+            Backend::Auto | Backend::Scp => copy_scp(&group, dst_location, scan.as_ref(), options),
+            Backend::Rsync => copy_rsync(&group, dst_location, scan.as_ref(), filter, options),
+            Backend::Native => copy_native(&group, dst_location, scan.as_ref(), options),
+            Backend::Tar => copy_tar(&group, dst_location, scan.as_ref(), filter, options),
+        })?;
+
+        total.success &= summary.success;
+        total.files = total.files.zip(summary.files).map(|(a, b)| a + b);
+        total.bytes = total.bytes.zip(summary.bytes).map(|(a, b)| a + b);
+        total.elapsed += summary.elapsed;
+        if !summary.success {
+            break;
+        }
+
+        if verify_group {
+            let report = verify::verify(&pairs, filter)?;
+            verified += report.verified;
+            mismatched.extend(report.mismatched);
+            checksums.extend(report.checksums);
+        }
+    }
+
+    // 結果の表示
+    if !total.success {
+        // バックグラウンドジョブなどで失敗を判別できるよう、終了コードに反映する
+        return Err("コピーに失敗しました".into());
+    }
+    // 検証に失敗した場合は完了を表示しない
+    if !mismatched.is_empty() {
+        println!("{}: {} ファイルのチェックサムが一致しません", "ERROR".red(), mismatched.len());
+        for path in &mismatched {
+            println!("  {}", path.red());
+        }
+        return Err(Error::ChecksumMismatch(mismatched).into());
+    }
+    println!("{}: コピーが正常に完了しました", "INFO".green());
+    print_summary(total);
+    if options.verify_enabled() && options.backend != Backend::Native {
+        println!("{}: {} ファイルのチェックサムが一致しました", "INFO".green(), verified);
+    }
+
+    Ok(())
+}
+
+/// リモートのファイルをローカルに取得します
+///
+/// # 引数
+/// * `src` - コピー元（host:エイリアスまたはhost:パス）
+/// * `local` - コピー先のローカルパス（省略時はカレントディレクトリ）
+/// * `options` - 転送オプション
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn pull(src: &str, local: Option<&str>, options: &CopyOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.contains(':') {
+        return Err(format!("'{}' はリモートのパス指定ではありません（host:path 形式で指定してください）", src).into());
+    }
+    copy_files(&[src.to_string()], local.unwrap_or("."), options)
+}
+
+/// ローカルのファイルをホストに送信します
+///
+/// コピー先にパスを省略した場合（`host` のみ）、ホストの `default_upload` を使用します。
+///
+/// # 引数
+/// * `sources` - コピー元のローカルパス（1つ以上）
+/// * `dst` - コピー先（host、host:エイリアスまたはhost:パス）
+/// * `options` - 転送オプション
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn push(sources: &[String], dst: &str, options: &CopyOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if dst.contains(':') {
+        return copy_files(sources, dst, options);
+    }
+
+    let config = Config::load()?;
+    let host = config
+        .hosts
+        .get(dst)
+        .ok_or_else(|| format!("ホスト '{}' が見つかりません", dst))?;
+    let Some(ref upload) = host.default_upload else {
+        return Err(format!(
+            "ホスト '{}' に default_upload が設定されていません（'sshportal set-upload {} <エイリアス>' で設定してください）",
+            dst, dst
+        )
+        .into());
+    };
+    copy_files(sources, &format!("{}:{}", dst, upload), options)
+}
+
+/// "host:" で終わる引数をブラウザで選択したパスに置き換えます（`--browse`）
+///
+/// ブラウザは対話的な入力が必要なため、標準入力と標準出力が端末でない場合はエラーにします。
+/// ブラウザを中止した場合もエラーを返します。
+fn browse_placeholders(config: &Config, args: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut resolved = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg.strip_suffix(':').filter(|host| !host.is_empty() && !host.contains(':')) {
+            Some(host) => {
+                if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+                    return Err("--browse にはリモートディレクトリブラウザを操作する端末が必要です".into());
+                }
+                let endpoint = resolve::resolve_host(config, host)?;
+                let Some(path) = browser::browse(&endpoint, "")? else {
+                    return Err("キャンセルされました".into());
+                };
+                resolved.push(format!("{}:{}", host, path));
+            }
+            None => resolved.push(arg.clone()),
+        }
+    }
+    Ok(resolved)
+}
+
+/// 転送に適用する除外・包含パターンを集めます
+///
+/// コマンドラインのパターン、コピー元に使用したエイリアスのデフォルトの除外パターン、
+/// ディレクトリのコピー元のルートにある `.sshportalignore` を合わせます。
+/// リモートの `.sshportalignore` は接続先ごとに1回のssh接続で取得し、
+/// `--print` の場合は取得しません。
+fn build_filter(
+    config: &Config,
+    specs: &[String],
+    sources: &[Location],
+    options: &CopyOptions,
+) -> Result<TransferFilter, Box<dyn std::error::Error>> {
+    let mut filter = TransferFilter {
+        include: options.include.clone(),
+        exclude: options.exclude.clone(),
+    };
+
+    for spec in specs {
+        if let Some(patterns) = resolve::alias_name(spec).and_then(|name| config.excludes.get(name)) {
+            filter.extend(TransferFilter {
+                include: Vec::new(),
+                exclude: patterns.clone(),
+            });
+        }
+    }
+
+    for group in sources.chunk_by(|a, b| a.endpoint() == b.endpoint()) {
+        match group[0].endpoint() {
+            // --print ではssh接続を行わないため、リモートの .sshportalignore は考慮しない
+            Some(_) if options.print => {}
+            Some(endpoint) => {
+                // ディレクトリのコピー元のみを対象に、1回のssh接続でまとめて取得する
+                let script: Vec<String> = group
+                    .iter()
+                    .filter(|src| !resolve::has_glob(src.path()))
+                    .map(|src| {
+                        let dir = argv::quote_literal(match src.path() {
+                            "" => ".",
+                            path => path,
+                        });
+                        format!("if [ -d {d} ]; then cat -- {d}/{f} 2>/dev/null; fi", d = dir, f = IGNORE_FILE)
+                    })
+                    .collect();
+                if script.is_empty() {
+                    continue;
+                }
+                let content = remote::run(endpoint, &format!("{}; true", script.join("; ")))?;
+                filter.extend(TransferFilter::parse_ignore_file(&content));
+            }
+            None => {
+                for dir in group.iter().map(|src| Path::new(src.path())).filter(|path| path.is_dir()) {
+                    let file = dir.join(IGNORE_FILE);
+                    if file.is_file() {
+                        filter.extend(TransferFilter::parse_ignore_file(&fs::read_to_string(file)?));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(filter)
+}
+
+/// 転送が失敗した場合に、間隔を倍増させながら再試行します
+///
+/// 間隔は1秒から始めて倍増し、最大60秒です。
+///
+/// # 引数
+/// * `retries` - 再試行する回数（0の場合は再試行しない）
+/// * `transfer` - 1回分の転送
+///
+/// # 戻り値
+/// 最後の試行の結果（経過時間は全ての試行の合計）
+fn with_retries<F>(retries: u32, mut transfer: F) -> Result<TransferSummary, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Result<TransferSummary, Box<dyn std::error::Error>>,
+{
+    let mut elapsed = Duration::ZERO;
+    let mut attempt = 0;
+    loop {
+        let result = transfer();
+        let reason = match result {
+            Ok(mut summary) if summary.success || attempt == retries => {
+                summary.elapsed += elapsed;
+                return Ok(summary);
+            }
+            // 転送の組み合わせがサポートされていない場合や、再開したファイルの検証に失敗した場合は
+            // 再試行しても成功しない
+            Err(e)
+                if attempt == retries
+                    || matches!(e.downcast_ref::<Error>(), Some(Error::Unsupported(_) | Error::ChecksumMismatch(_))) =>
+            {
+                return Err(e)
+            }
+            Ok(summary) => {
+                elapsed += summary.elapsed;
+                "転送に失敗しました".to_string()
+            }
+            Err(e) => e.to_string(),
+        };
+
+        let delay = Duration::from_secs((1u64 << attempt.min(6)).min(60));
+        attempt += 1;
+        println!(
+            "{}: {}。{}秒後に再試行します（{}/{}）",
+            "WARN".yellow(),
+            reason,
+            delay.as_secs(),
+            attempt,
+            retries
+        );
+        std::thread::sleep(delay);
+    }
+}
+
+/// 上書きされるファイルを求め、オプションまたはプロンプトに従って扱いを決めます
+///
+/// 退避を選んだ場合はこの関数内で既存のファイルを退避します。スキップする場合は、
+/// 各組の `skipped` にスキップするファイルを設定します。
+/// 標準入力が端末でない場合は、従来どおり確認せずに上書きします。
+///
+/// # 戻り値
+/// 既存のファイルをスキップする場合はtrue
+fn resolve_conflicts(
+    pairs: &mut [verify::VerifyPair],
+    filter: &TransferFilter,
+    options: &CopyOptions,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let conflicts = clobber::find(pairs, filter)?;
+    let files: Vec<String> = pairs
+        .iter()
+        .zip(&conflicts)
+        .flat_map(|(pair, files)| files.iter().map(|relative| clobber::display_path(pair, relative)))
+        .collect();
+
+    let resolution = if options.no_clobber {
+        Resolution::Skip
+    } else if files.is_empty() {
+        Resolution::Overwrite
+    } else if options.backup {
+        Resolution::Backup
+    } else if io::stdin().is_terminal() {
+        clobber::prompt(&files)?
+    } else {
+        println!("{}: 既存の{}件のファイルを上書きします", "WARN".yellow(), files.len());
+        Resolution::Overwrite
+    };
+
+    match resolution {
+        Resolution::Overwrite => Ok(false),
+        Resolution::Backup => {
+            clobber::backup(pairs, &conflicts)?;
+            Ok(false)
+        }
+        Resolution::Skip => {
+            if !files.is_empty() {
+                println!("{}: 既存の{}件のファイルをスキップします", "INFO".blue(), files.len());
+            }
+            for (pair, files) in pairs.iter_mut().zip(conflicts) {
+                pair.skipped = files;
+            }
+            Ok(true)
+        }
+        Resolution::Abort => Err("キャンセルされました".into()),
+    }
+}
+
+/// 除外パターンを考慮して使用するバックエンドを決定します
+///
+/// scpは除外パターンに対応しないため、パターンがある場合はrsyncに切り替えます。
+/// rsyncが使用できない場合（未インストール、リモート間のコピー）はtarストリーム転送に
+/// 切り替えます。tarは包含パターンに対応しないため、その場合はエラーを返します。
+fn filtered_backend(
+    backend: Backend,
+    filter: &TransferFilter,
+    sources: &[Location],
+    dst: &Location,
+) -> Result<Backend, Box<dyn std::error::Error>> {
+    if filter.is_empty() {
+        return Ok(backend);
+    }
+    match backend {
+        Backend::Rsync => Ok(Backend::Rsync),
+        Backend::Tar if !filter.include.is_empty() => {
+            Err("tar バックエンドは --include に対応していません（--backend rsync を使用してください）".into())
+        }
+        Backend::Tar => Ok(Backend::Tar),
+        Backend::Native => {
+            Err("native バックエンドは除外パターンに対応していません（--backend rsync を使用してください）".into())
+        }
+        Backend::Auto | Backend::Scp => {
+            let remote_to_remote = !dst.is_local() && sources.iter().any(|s| !s.is_local());
+            if !remote_to_remote && argv::is_available("rsync") {
+                println!("{}: scp は除外パターンに対応していないため、rsync で転送します", "INFO".blue());
+                return Ok(Backend::Rsync);
+            }
+            if !filter.include.is_empty() {
+                return Err("--include を使用するには rsync が必要です（リモート間のコピーでは使用できません）".into());
+            }
+            println!("{}: scp は除外パターンに対応していないため、tar ストリームで転送します", "INFO".blue());
+            Ok(Backend::Tar)
+        }
+    }
+}
+
+/// `auto` の場合に使用するバックエンドを決定します
+///
+/// rsyncはsshportalのプログレスバーを表示できるため、ローカルとリモートの両方で使用できる
+/// 場合はrsyncを、それ以外（リモート間のコピーを含む）はscpを使用します。
+///
+/// # 引数
+/// * `sources` - コピー元（同じ接続先）
+/// * `dst` - コピー先
+/// * `check_remote` - リモートにrsyncがあるかをssh接続して確認するか（falseの場合はあるとみなす）
+///
+/// # 戻り値
+/// rsyncまたはscp
+fn auto_backend(sources: &[Location], dst: &Location, check_remote: bool) -> Result<Backend, Box<dyn std::error::Error>> {
+    let remote_to_remote = !dst.is_local() && sources.iter().any(|s| !s.is_local());
+    if remote_to_remote || !argv::is_available("rsync") {
+        return Ok(Backend::Scp);
+    }
+    match sources.iter().chain(std::iter::once(dst)).find_map(Location::endpoint) {
+        Some(endpoint) if check_remote => {
+            let found = remote::run(endpoint, "command -v rsync >/dev/null 2>&1 && echo found; true")?;
+            Ok(if found.trim() == "found" { Backend::Rsync } else { Backend::Scp })
+        }
+        _ => Ok(Backend::Rsync),
+    }
+}
+
+/// scpから切り替えたバックエンドでも、scpと同じ配置になるようにコピー元を調整します
+///
+/// scpはコピー先が既存のディレクトリであればその中に置き、そうでなければコピー元そのものを
+/// コピー先の名前で作成します。rsyncでは、後者の場合はディレクトリのコピー元の末尾に `/` を
+/// 付けて内容をコピー先に転送し、前者の場合は末尾の `/` を除いてディレクトリごと置きます。
+/// tarは常にコピー先ディレクトリの中に置くため、後者の配置は再現できずエラーを返します。
+///
+/// # 引数
+/// * `sources` - コピー元（同じ接続先）
+/// * `dst` - コピー先
+/// * `requested` - 指定されたバックエンド（`auto` はscpの配置に従う）
+/// * `backend` - 実際に使用するバックエンド
+///
+/// # 戻り値
+/// 調整したコピー元（scpから切り替えていない場合はそのまま）
+fn scp_layout(
+    sources: &[Location],
+    dst: &Location,
+    requested: Backend,
+    backend: Backend,
+) -> Result<Vec<Location>, Box<dyn std::error::Error>> {
+    if !matches!(requested, Backend::Auto | Backend::Scp) || backend == Backend::Scp {
+        return Ok(sources.to_vec());
+    }
+
+    let is_dir = |location: &Location| -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(match location {
+            Location::Local(path) => Path::new(path).is_dir(),
+            Location::Remote { endpoint, path } => remote::stat_kind(endpoint, path)? == Some(remote::PathKind::Dir),
+        })
+    };
+    let with_path = |location: &Location, path: String| match location {
+        Location::Local(_) => Location::Local(path),
+        Location::Remote { endpoint, .. } => Location::Remote {
+            endpoint: endpoint.clone(),
+            path,
+        },
+    };
+
+    // コピー先がディレクトリの場合は、いずれのバックエンドもコピー元をその中に置く
+    if sources.len() > 1 || is_dir(dst)? {
+        return Ok(sources
+            .iter()
+            .map(|src| match src.path().trim_end_matches('/') {
+                "" => src.clone(),
+                path => with_path(src, path.to_string()),
+            })
+            .collect());
+    }
+
+    let src = &sources[0];
+    if backend == Backend::Tar {
+        return Err(Error::Unsupported(format!(
+            "tar ストリームでは '{}' を既存のディレクトリ以外のコピー先 '{}' に scp と同じ配置で転送できません\
+             （コピー先に既存のディレクトリを指定するか、rsync を使用してください）",
+            src.to_arg(),
+            dst.to_arg()
+        ))
+        .into());
+    }
+    if resolve::has_glob(src.path()) || src.path().ends_with('/') || !is_dir(src)? {
+        return Ok(sources.to_vec());
+    }
+    Ok(vec![with_path(src, format!("{}/", src.path()))])
+}
+
+/// ローカルのコピー元を事前にスキャンします
+///
+/// リモートのコピー元を含む場合やスキャンに失敗した場合はNoneを返します。
+fn scan_sources(sources: &[Location]) -> Option<LocalScan> {
+    let mut scan = LocalScan::default();
+    for src in sources {
+        let Location::Local(path) = src else { return None };
+        scan.files.extend(progress::scan_local(Path::new(path)).ok()?.files);
+    }
+    Some(scan)
+}
+
+/// scpでコピーします
+///
+/// scpは機械可読な進捗を出力しないため、転送中はscp自身のファイルごとの進捗表示に任せ、
+/// 一覧と概要には事前スキャンの結果（ローカルのコピー元のみ）を使用します。
+/// 既定の `auto` ではrsyncを使用できない場合のみscpで転送します。
+fn copy_scp(
+    sources: &[Location],
+    dst: &Location,
+    scan: Option<&LocalScan>,
+    options: &CopyOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.verbose {
+        if let Some(scan) = scan {
+            for (path, size) in &scan.files {
+                println!("  {} ({})", path.display(), progress::format_bytes(*size));
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let status = argv::command(&argv::scp_argv(sources, dst)).status()?;
+    Ok(TransferSummary {
+        success: status.success(),
+        files: scan.map(|s| s.files.len() as u64),
+        bytes: scan.map(|s| s.total_bytes()),
+        elapsed: started.elapsed(),
+    })
+}
+
+/// rsyncでコピーします
+///
+/// `--info=progress2` の出力を解析してプログレスバーを更新します。
+fn copy_rsync(
+    sources: &[Location],
+    dst: &Location,
+    scan: Option<&LocalScan>,
+    filter: &TransferFilter,
+    options: &CopyOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let rsync = rsync_command(sources, dst, filter, options)?;
+
+    let mut child = argv::command(&rsync).stdout(Stdio::piped()).spawn()?;
+    let mut bar = ProgressBar::new(scan.map(|s| s.total_bytes()));
+
+    // rsyncは進捗行を\rで上書きするため、\rと\nの両方で行を区切る
+    if let Some(stdout) = child.stdout.take() {
+        let mut reader = BufReader::new(stdout);
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            let n = read_until_any(&mut reader, &mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&buffer);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() {
+                continue;
+            }
+            match progress::parse_rsync_progress(line) {
+                Some((bytes, files)) => {
+                    if let Some(files) = files {
+                        bar.set_files(files);
+                    }
+                    bar.set_position(bytes);
+                }
+                None if options.verbose => bar.println(&format!("  {}", line)),
+                None => {}
+            }
+        }
+    }
+    let status = child.wait()?;
+    bar.finish();
+
+    Ok(TransferSummary {
+        success: status.success(),
+        files: Some(bar.files()),
+        bytes: Some(bar.position()),
+        elapsed: bar.elapsed(),
+    })
+}
+
+/// tarストリームでコピーします
+///
+/// 転送中の進捗は取得できないため、事前スキャンの結果を一覧と概要に使用します。
+fn copy_tar(
+    sources: &[Location],
+    dst: &Location,
+    scan: Option<&LocalScan>,
+    filter: &TransferFilter,
+    options: &CopyOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    if options.verbose {
+        if let Some(scan) = scan {
+            for (path, size) in &scan.files {
+                println!("  {} ({})", path.display(), progress::format_bytes(*size));
+            }
+        }
+    }
+
+    let started = Instant::now();
+    tar_transfer::copy(sources, dst, options.compress, &filter.exclude)?;
+    Ok(TransferSummary {
+        success: true,
+        files: scan.map(|s| s.files.len() as u64),
+        bytes: scan.map(|s| s.total_bytes()),
+        elapsed: started.elapsed(),
+    })
+}
+
+/// 進捗表示・除外パターン・再開・上書き防止のオプションを含むrsyncのargvを構築します
+fn rsync_command(
+    sources: &[Location],
+    dst: &Location,
+    filter: &TransferFilter,
+    options: &CopyOptions,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut rsync = argv::rsync_argv(sources, dst)?;
+    let info = if options.verbose { "--info=progress2,name1" } else { "--info=progress2,name0" };
+    rsync.insert(1, info.to_string());
+    rsync.splice(2..2, filter.rsync_args());
+    if options.resume {
+        // 中断したファイルを残し、次回は続きから追記して全体を検証する
+        rsync.splice(2..2, ["--partial".to_string(), "--append-verify".to_string()]);
+    }
+    if options.no_clobber {
+        rsync.insert(2, "--ignore-existing".to_string());
+    }
+    Ok(rsync)
+}
+
+/// 接続先ごとの帯域制限・圧縮・暗号方式の実効値と、実行するコマンドを表示します
+///
+/// 各値にはコマンドラインとホスト設定のどちらで指定されたかを併記します。
+fn print_effective(
+    config: &Config,
+    sources: &[Location],
+    dst: &Location,
+    filter: &TransferFilter,
+    options: &CopyOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut endpoints: Vec<&resolve::Endpoint> = Vec::new();
+    for endpoint in sources.iter().chain(std::iter::once(dst)).filter_map(Location::endpoint) {
+        if !endpoints.contains(&endpoint) {
+            endpoints.push(endpoint);
+        }
+    }
+
+    println!("{}", "実効設定:".bold());
+    for endpoint in endpoints {
+        let host = endpoint.alias.as_ref().and_then(|alias| config.hosts.get(alias));
+        let origin = |flag: bool, configured: bool| {
+            if flag {
+                "（コマンドライン）".dimmed()
+            } else if configured {
+                "（ホスト設定）".dimmed()
+            } else {
+                "".dimmed()
+            }
+        };
+        println!(
+            "  {} ({})",
+            endpoint.alias.as_deref().unwrap_or(&endpoint.connection).cyan(),
+            endpoint.connection
+        );
+        let bwlimit = match endpoint.bwlimit {
+            Some(limit) => format!("{} KiB/s", limit),
+            None => "無制限".to_string(),
+        };
+        println!(
+            "    帯域制限: {}{}",
+            bwlimit,
+            origin(options.bwlimit.is_some(), host.is_some_and(|h| h.bwlimit.is_some()))
+        );
+        println!(
+            "    圧縮: {}{}",
+            if endpoint.compression { "有効" } else { "無効" },
+            origin(
+                options.ssh_compression || options.no_ssh_compression,
+                host.is_some_and(|h| h.compression.is_some())
+            )
+        );
+        println!(
+            "    暗号方式: {}{}",
+            endpoint.cipher.as_deref().unwrap_or("既定"),
+            origin(options.cipher.is_some(), host.is_some_and(|h| h.cipher.is_some()))
+        );
+    }
+
+    println!("{}", "コマンド:".bold());
+    for group in sources.chunk_by(|a, b| a.endpoint() == b.endpoint()) {
+        // --print ではリモートのrsyncの有無を確認せず、あるとみなす
+        let requested = match options.backend {
+            Backend::Auto => auto_backend(group, dst, false)?,
+            backend => backend,
+        };
+        let backend = filtered_backend(requested, filter, group, dst)?;
+        let group = &scp_layout(group, dst, options.backend, backend)?;
+        let command = match backend {
+            Backend::Auto | Backend::Scp => argv::scp_argv(group, dst),
+            Backend::Rsync => rsync_command(group, dst, filter, options)?,
+            Backend::Native => vec!["（russhによるSFTP転送）".to_string()],
+            Backend::Tar => vec!["（tarストリーム転送）".to_string()],
+        };
+        let command: Vec<String> = command.iter().map(|a| argv::shell_quote(a)).collect();
+        println!("  {}", command.join(" "));
+    }
+    Ok(())
+}
+
+/// ネイティブSFTP転送でコピーします
+#[cfg(feature = "native")]
+fn copy_native(
+    sources: &[Location],
+    dst: &Location,
+    scan: Option<&LocalScan>,
+    options: &CopyOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    let transfer_options = crate::native::TransferOptions {
+        resume: options.resume,
+        verify: options.verify_enabled(),
+    };
+
+    let mut bar = ProgressBar::new(scan.map(|s| s.total_bytes()));
+    let mut on_progress = |event: progress::ProgressEvent| {
+        if let progress::ProgressEvent::FileStarted { path, size } = event {
+            if options.verbose {
+                bar.println(&format!("  {} ({})", path.display(), progress::format_bytes(size)));
+            }
+        }
+        bar.handle(event);
+    };
+    let result = crate::native::copy(sources, dst, &transfer_options, &mut on_progress);
+    bar.finish();
+    result?;
+
+    Ok(TransferSummary {
+        success: true,
+        files: Some(bar.files()),
+        bytes: Some(bar.position()),
+        elapsed: bar.elapsed(),
+    })
+}
+
+/// ネイティブSFTP転送でコピーします（`native`フィーチャー無効時）
+#[cfg(not(feature = "native"))]
+fn copy_native(
+    _sources: &[Location],
+    _dst: &Location,
+    _scan: Option<&LocalScan>,
+    _options: &CopyOptions,
+) -> Result<TransferSummary, Box<dyn std::error::Error>> {
+    Err("ネイティブ転送を使用するには `native` フィーチャーを有効にしてビルドしてください".into())
+}
+
+/// \rまたは\nまでを読み込みます
+///
+/// # 戻り値
+/// 読み込んだバイト数（終端に達した場合は0）
+fn read_until_any<R: BufRead>(reader: &mut R, buffer: &mut Vec<u8>) -> std::io::Result<usize> {
+    let mut total = 0;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(total);
+        }
+        match available.iter().position(|&b| b == b'\r' || b == b'\n') {
+            Some(i) => {
+                buffer.extend_from_slice(&available[..=i]);
+                reader.consume(i + 1);
+                return Ok(total + i + 1);
+            }
+            None => {
+                let len = available.len();
+                buffer.extend_from_slice(available);
+                reader.consume(len);
+                total += len;
+            }
+        }
+    }
+}
+
+/// 転送結果の概要を表示します
+fn print_summary(summary: &TransferSummary) {
+    let files = summary
+        .files
+        .map(|n| format!("{} ファイル", n))
+        .unwrap_or_else(|| "ファイル数不明".to_string());
+    let size = match summary.bytes {
+        Some(bytes) => format!(
+            "{} ({}/s)",
+            progress::format_bytes(bytes),
+            progress::format_bytes(progress::rate(bytes, summary.elapsed) as u64)
+        ),
+        None => "サイズ不明".to_string(),
+    };
+    println!(
+        "  {}, {}, {}",
+        files,
+        size,
+        progress::format_duration(summary.elapsed).dimmed()
+    );
+}
@@ -0,0 +1,161 @@
+// 転送後の検証機能
+//
+// このモジュールは、転送後にコピー元とコピー先のファイルのSHA-256を比較し、
+// 転送内容が一致しているかをファイルごとに確認します。
+
+use crate::diff;
+use crate::filter::TransferFilter;
+use crate::remote::{self, PathKind};
+use crate::resolve::{self, Location};
+use crate::transfer::Backend;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// 検証するコピー元とコピー先の組
+pub struct VerifyPair {
+    /// コピー元のファイルまたはディレクトリ
+    pub src: Location,
+    /// 転送後にコピー元に対応するコピー先のファイルまたはディレクトリ
+    pub dst: Location,
+    /// 転送しなかったファイル（コピー元からの相対パス、`--no-clobber` でスキップしたもの）
+    pub skipped: BTreeSet<String>,
+}
+
+/// 検証の結果
+#[derive(Default)]
+pub struct VerifyReport {
+    /// 一致したファイル数
+    pub verified: u64,
+    /// 一致しなかった（またはコピー先に存在しない）ファイル
+    pub mismatched: Vec<String>,
+    /// 一致したファイルのコピー元のパスとSHA-256
+    pub checksums: Vec<(String, String)>,
+}
+
+/// 転送前に、各コピー元が転送後にどこに置かれるかを求めます
+///
+/// コピー先が既存のディレクトリかどうかで配置が変わるため、転送前に呼び出す必要があります。
+/// 配置はバックエンドごとの規則に従います:
+/// * scp - コピー先がディレクトリならその中に、そうでなければコピー先そのもの
+/// * rsync - 末尾が`/`のコピー元は内容をコピー先に、ディレクトリはコピー先の中に
+/// * tar - 常にコピー先ディレクトリの中に
+///
+/// # 引数
+/// * `sources` - コピー元（同じ接続先）
+/// * `dst` - コピー先
+/// * `backend` - 使用するバックエンド
+///
+/// # 戻り値
+/// 検証する組の一覧（リモートのglobなど対応先を特定できないコピー元は含まない）
+///
+/// 検証のほか、転送前の上書きの確認にも使用します。
+pub fn plan(sources: &[Location], dst: &Location, backend: Backend) -> Result<Vec<VerifyPair>, Box<dyn std::error::Error>> {
+    let dst_is_dir = sources.len() > 1
+        || match dst {
+            Location::Local(path) => Path::new(path).is_dir(),
+            Location::Remote { endpoint, path } => remote::stat_kind(endpoint, path)? == Some(PathKind::Dir),
+        };
+
+    let mut pairs = Vec::new();
+    for src in sources {
+        if !src.is_local() && resolve::has_glob(src.path()) {
+            continue;
+        }
+
+        let name = source_name(src.path());
+        let contents_only = src.path().ends_with('/');
+        // rsyncはディレクトリを新しいコピー先の中に置くため、コピー元の種類を確認する
+        let src_is_dir = match src {
+            Location::Local(path) => Path::new(path).is_dir(),
+            Location::Remote { endpoint, path } if backend == Backend::Rsync && !dst_is_dir => {
+                remote::stat_kind(endpoint, path)? == Some(PathKind::Dir)
+            }
+            Location::Remote { .. } => false,
+        };
+        let into_dst = match backend {
+            Backend::Tar => true,
+            Backend::Rsync => !contents_only && (dst_is_dir || src_is_dir),
+            _ => dst_is_dir,
+        };
+
+        let dst_path = match name {
+            Some(name) if into_dst => join(dst.path(), &name),
+            _ => dst.path().to_string(),
+        };
+        pairs.push(VerifyPair {
+            src: src.clone(),
+            dst: match dst {
+                Location::Local(_) => Location::Local(dst_path),
+                Location::Remote { endpoint, .. } => Location::Remote {
+                    endpoint: endpoint.clone(),
+                    path: dst_path,
+                },
+            },
+            skipped: BTreeSet::new(),
+        });
+    }
+    Ok(pairs)
+}
+
+/// コピー元とコピー先のファイルのSHA-256をファイルごとに比較します
+///
+/// コピー先にだけ存在するファイルは無視します。除外パターンに一致する
+/// コピー元のファイルは転送されないため比較しません。
+///
+/// # 引数
+/// * `pairs` - 検証する組
+/// * `filter` - 転送時に適用した除外・包含パターン
+///
+/// # 戻り値
+/// 検証の結果
+pub fn verify(pairs: &[VerifyPair], filter: &TransferFilter) -> Result<VerifyReport, Box<dyn std::error::Error>> {
+    let matcher = filter.matcher()?;
+    let mut report = VerifyReport::default();
+
+    for pair in pairs {
+        let src_tree = diff::location_tree(&pair.src)?;
+        let dst_tree = diff::location_tree(&pair.dst)?;
+
+        for (relative, digest) in &src_tree {
+            if (!relative.is_empty() && matcher.is_ignored(Path::new(relative), false)) || pair.skipped.contains(relative) {
+                continue;
+            }
+            if dst_tree.get(relative) == Some(digest) {
+                report.verified += 1;
+                report.checksums.push((child_path(&pair.src, relative), digest.sha256.clone()));
+            } else {
+                report.mismatched.push(child_path(&pair.dst, relative));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// 場所の下の相対パスを表示用の引数形式で返します（相対パスが空の場合は場所そのもの）
+fn child_path(location: &Location, relative: &str) -> String {
+    if relative.is_empty() {
+        location.to_arg()
+    } else {
+        format!("{}/{}", location.to_arg().trim_end_matches('/'), relative)
+    }
+}
+
+/// コピー元のパスの名前（最後の要素）を返します
+///
+/// `.`や`~`のように名前を持たないパスはNoneを返します。
+fn source_name(path: &str) -> Option<String> {
+    let name = path.trim_end_matches('/').rsplit('/').next()?;
+    match name {
+        "" | "." | ".." | "~" => None,
+        name => Some(name.to_string()),
+    }
+}
+
+/// パスに名前を連結します
+fn join(dir: &str, name: &str) -> String {
+    match dir.trim_end_matches('/') {
+        "" if dir.starts_with('/') => format!("/{}", name),
+        "" => name.to_string(),
+        dir => format!("{}/{}", dir, name),
+    }
+}
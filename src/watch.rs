@@ -0,0 +1,409 @@
+// 監視同期機能
+//
+// このモジュールは、ローカルディレクトリの変更をファイルシステムの通知で監視し、
+// 変更されたファイルをまとめてリモートのディレクトリにアップロードします。
+
+use crate::argv;
+use crate::audit::{self, AuditRecord};
+use crate::checksum;
+use crate::config::Config;
+use crate::filter::PathFilter;
+use crate::progress;
+use crate::remote;
+use crate::resolve::{self, Endpoint, Location};
+use crate::safety;
+use colored::*;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// 監視同期のオプション
+#[derive(Debug, Clone, clap::Args)]
+pub struct WatchOptions {
+    /// ローカルで削除されたファイルをリモートからも削除する
+    #[arg(long, help = "ローカルで削除されたファイルをリモートからも削除")]
+    pub delete: bool,
+    /// 追加の除外パターン
+    #[arg(long = "ignore", value_name = "PATTERN", help = "除外パターン（.gitignore形式、複数指定可）")]
+    pub ignore: Vec<String>,
+    /// 保護されたホストへの書き込みの確認を省略する（環境変数での許可が必要）
+    #[arg(short = 'y', long, help = "保護されたホストへの書き込みの確認を省略（SSHPORTAL_ALLOW_YES=1が必要）")]
+    pub yes: bool,
+    /// 変更をまとめるための待ち時間（ミリ秒）
+    #[arg(long, default_value_t = 300, value_name = "MS", help = "変更をまとめる待ち時間（ミリ秒）")]
+    pub debounce: u64,
+}
+
+/// 同期先
+struct SyncTarget {
+    endpoint: Endpoint,
+    dir: String,
+    /// 入力されたままの同期先（監査ログ用）
+    spec: String,
+    /// 監査ログのパス
+    audit_log: PathBuf,
+}
+
+/// 同期の累計
+#[derive(Default)]
+struct WatchStatus {
+    uploaded: u64,
+    deleted: u64,
+    errors: u64,
+    last_sync: Option<String>,
+}
+
+/// ローカルディレクトリを監視し、変更をリモートに同期します
+///
+/// 変更通知を `debounce` ミリ秒の間まとめてから、変更・作成されたファイルだけを
+/// アップロードします。基準ディレクトリの `.gitignore` と `--ignore` のパターンに
+/// 一致するファイル、および `.git` ディレクトリは同期しません。
+///
+/// # 引数
+/// * `local_spec` - 監視するローカルディレクトリ（エイリアスまたはパス）
+/// * `dst_spec` - 同期先（host:エイリアスまたはhost:パス）
+/// * `options` - 監視同期のオプション
+///
+/// # 戻り値
+/// 成功時は()、失敗時はエラーを返します。
+pub fn watch(local_spec: &str, dst_spec: &str, options: &WatchOptions) -> Result<(), Box<dyn std::error::Error>> {
+    // 現在の設定を読み込み
+    let config = Config::load()?;
+
+    let Location::Local(local) = resolve::resolve_spec(&config, local_spec)? else {
+        println!("{}: 監視元はローカルのディレクトリを指定してください", "ERROR".red());
+        return Ok(());
+    };
+    let Location::Remote { endpoint, path } = resolve::resolve_spec(&config, dst_spec)? else {
+        println!("{}: 同期先は host:パス の形式で指定してください", "ERROR".red());
+        return Ok(());
+    };
+    if !Path::new(&local).is_dir() {
+        println!("{}: '{}' はディレクトリではありません", "ERROR".red(), local);
+        return Ok(());
+    }
+    if let Err(e) = safety::check_write(&config, &endpoint, options.yes) {
+        let destination = Location::Remote { endpoint, path };
+        let record = AuditRecord::new("sync", &[local_spec.to_string()], dst_spec, "scp")
+            .locations(&[Location::Local(local)], &destination);
+        return audit::refused(&config, record, e);
+    }
+
+    // 通知されるパスと比較できるよう正規化する（macOSの /private など）
+    let root = fs::canonicalize(&local)?;
+    let filter = PathFilter::new(&root, &options.ignore)?;
+    let target = SyncTarget {
+        endpoint,
+        dir: path,
+        spec: dst_spec.to_string(),
+        audit_log: audit::log_file(&config)?,
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+
+    println!(
+        "{}: {} → {}:{} を監視しています（Ctrl+Cで終了）",
+        "INFO".green(),
+        root.display(),
+        target.endpoint.connection,
+        target.dir
+    );
+
+    let debounce = Duration::from_millis(options.debounce);
+    let mut status = WatchStatus::default();
+    draw_status(&status);
+
+    while let Ok(event) = rx.recv() {
+        // 最初の通知から、通知が途切れるまでの変更をまとめる
+        let mut changed = BTreeSet::new();
+        collect_paths(event, &mut changed);
+        loop {
+            match rx.recv_timeout(debounce) {
+                Ok(event) => collect_paths(event, &mut changed),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        sync_batch(&root, &filter, &target, options.delete, &changed, &mut status);
+        draw_status(&status);
+    }
+
+    Ok(())
+}
+
+/// 通知から変更されたパスを取り出します
+///
+/// 読み込みのみの通知（アップロード時のファイル読み込みを含む）は無視します。
+fn collect_paths(event: notify::Result<notify::Event>, changed: &mut BTreeSet<PathBuf>) {
+    match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => changed.extend(event.paths),
+        Ok(_) => {}
+        Err(e) => print_line(&format!("{}: 監視エラー: {}", "WARN".yellow(), e)),
+    }
+}
+
+/// まとめた変更をリモートに反映します
+///
+/// 現在も存在するパスはアップロードし、存在しないパスは `delete` の場合のみ削除します。
+fn sync_batch(
+    root: &Path,
+    filter: &PathFilter,
+    target: &SyncTarget,
+    delete: bool,
+    changed: &BTreeSet<PathBuf>,
+    status: &mut WatchStatus,
+) {
+    let mut uploads = BTreeSet::new();
+    let mut deletes = BTreeSet::new();
+
+    for path in changed {
+        let Some(relative) = relative_path(root, path) else { continue };
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => {
+                if filter.is_ignored(path, true) {
+                    continue;
+                }
+                // 新しく作成されたディレクトリは中のファイルごとアップロードする
+                if let Ok(scan) = progress::scan_local(path) {
+                    for (file, _) in scan.files {
+                        if !filter.is_ignored(&file, false) {
+                            uploads.extend(relative_path(root, &file));
+                        }
+                    }
+                }
+            }
+            Ok(_) => {
+                if !filter.is_ignored(path, false) {
+                    uploads.insert(relative);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if delete && !filter.is_ignored(path, false) {
+                    deletes.insert(relative);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
+    if uploads.is_empty() && deletes.is_empty() {
+        return;
+    }
+
+    let time = chrono::Local::now().format("%H:%M:%S").to_string();
+    if !uploads.is_empty() {
+        let result = upload_files(root, target, &uploads);
+        record_sync(root, target, "sync", &uploads, &result);
+        match result {
+            Ok(()) => {
+                status.uploaded += uploads.len() as u64;
+                print_line(&format!("[{}] {} {}", time, "↑".green(), summarize(&uploads)));
+            }
+            Err(e) => {
+                status.errors += 1;
+                print_line(&format!("[{}] {}: アップロードに失敗しました: {}", time, "ERROR".red(), e));
+            }
+        }
+    }
+    if !deletes.is_empty() {
+        let result = delete_files(target, &deletes);
+        record_sync(root, target, "sync-delete", &deletes, &result);
+        match result {
+            Ok(()) => {
+                status.deleted += deletes.len() as u64;
+                print_line(&format!("[{}] {} {}", time, "✗".red(), summarize(&deletes)));
+            }
+            Err(e) => {
+                status.errors += 1;
+                print_line(&format!("[{}] {}: 削除に失敗しました: {}", time, "ERROR".red(), e));
+            }
+        }
+    }
+    status.last_sync = Some(time);
+}
+
+/// ファイルをリモートにアップロードします
+///
+/// 親ディレクトリを1回のssh接続でまとめて作成し、親ディレクトリごとにscpで転送します。
+fn upload_files(root: &Path, target: &SyncTarget, files: &BTreeSet<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut groups: BTreeMap<String, Vec<Location>> = BTreeMap::new();
+    for file in files {
+        let parent = file.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+        groups
+            .entry(parent.to_string())
+            .or_default()
+            .push(Location::Local(root.join(file).to_string_lossy().to_string()));
+    }
+
+    let dirs: Vec<String> = groups
+        .keys()
+        .map(|parent| argv::quote_literal(&remote_join(&target.dir, parent)))
+        .collect();
+    remote::run(&target.endpoint, &format!("mkdir -p -- {}", dirs.join(" ")))?;
+
+    for (parent, sources) in &groups {
+        let output = argv::command(&upload_argv(target, parent, sources)).output()?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+        }
+    }
+    Ok(())
+}
+
+/// 同期先のディレクトリにファイルをアップロードするscpの引数を返します
+///
+/// コピー先のディレクトリ名はglobとして展開されないよう、リテラルとしてクォートします。
+fn upload_argv(target: &SyncTarget, parent: &str, sources: &[Location]) -> Vec<String> {
+    let dst = Location::Remote {
+        endpoint: target.endpoint.clone(),
+        path: remote_join(&target.dir, parent),
+    };
+    argv::scp_argv(sources, &dst)
+}
+
+/// ファイルをリモートから削除します
+fn delete_files(target: &SyncTarget, files: &BTreeSet<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let paths: Vec<String> = files
+        .iter()
+        .map(|file| argv::quote_literal(&remote_join(&target.dir, file)))
+        .collect();
+    remote::run(&target.endpoint, &format!("rm -rf -- {}", paths.join(" ")))?;
+    Ok(())
+}
+
+/// 同期したファイルを監査ログに記録します
+///
+/// アップロードしたファイルは合計サイズと、`sha256sum` 形式の一覧のSHA-256を記録します。
+fn record_sync(
+    root: &Path,
+    target: &SyncTarget,
+    action: &str,
+    files: &BTreeSet<String>,
+    result: &Result<(), Box<dyn std::error::Error>>,
+) {
+    let sources: Vec<Location> = files
+        .iter()
+        .map(|file| Location::Local(root.join(file).to_string_lossy().to_string()))
+        .collect();
+    let destination = Location::Remote {
+        endpoint: target.endpoint.clone(),
+        path: target.dir.clone(),
+    };
+    let typed: Vec<String> = files.iter().cloned().collect();
+    let backend = if action == "sync" { "scp" } else { "ssh" };
+    let mut record = AuditRecord::new(action, &typed, &target.spec, backend)
+        .locations(&sources, &destination)
+        .result(result);
+
+    if action == "sync" && result.is_ok() {
+        let mut bytes = 0;
+        let mut manifest = String::new();
+        for (file, source) in files.iter().zip(&sources) {
+            if let Ok(data) = fs::read(source.path()) {
+                bytes += data.len() as u64;
+                manifest.push_str(&format!("{}  {}\n", checksum::sha256_hex(&data), file));
+            }
+        }
+        record.bytes = Some(bytes);
+        record.checksum = Some(checksum::sha256_hex(manifest.as_bytes()));
+    }
+    audit::append(&target.audit_log, &record);
+}
+
+/// 基準ディレクトリからの相対パス（`/`区切り）を返します
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// リモートのディレクトリと相対パスを連結します
+fn remote_join(dir: &str, relative: &str) -> String {
+    match (dir.trim_end_matches('/'), relative) {
+        (dir, "") => dir.to_string(),
+        ("", relative) => relative.to_string(),
+        (dir, relative) => format!("{}/{}", dir, relative),
+    }
+}
+
+/// 変更されたファイルの一覧を1行にまとめます
+fn summarize(files: &BTreeSet<String>) -> String {
+    const MAX_LISTED: usize = 5;
+    let listed: Vec<&str> = files.iter().take(MAX_LISTED).map(String::as_str).collect();
+    if files.len() > MAX_LISTED {
+        format!("{} ほか{}件", listed.join(", "), files.len() - MAX_LISTED)
+    } else {
+        listed.join(", ")
+    }
+}
+
+/// ステータス行を消してからメッセージを1行表示します
+fn print_line(message: &str) {
+    if io::stderr().is_terminal() {
+        eprint!("\r\x1b[2K");
+    }
+    println!("{}", message);
+}
+
+/// 現在の同期状況をステータス行に表示します
+///
+/// 標準エラー出力が端末の場合のみ表示します。
+fn draw_status(status: &WatchStatus) {
+    if !io::stderr().is_terminal() {
+        return;
+    }
+    let mut line = format!(
+        "{} アップロード: {}  削除: {}  最終同期: {}",
+        "監視中".cyan(),
+        status.uploaded,
+        status.deleted,
+        status.last_sync.as_deref().unwrap_or("-")
+    );
+    if status.errors > 0 {
+        line.push_str(&format!("  {}", format!("エラー: {}", status.errors).red()));
+    }
+    eprint!("\r\x1b[2K{}", line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(dir: &str) -> SyncTarget {
+        SyncTarget {
+            endpoint: Endpoint::new("u@dev", 22),
+            dir: dir.to_string(),
+            spec: format!("dev:{}", dir),
+            audit_log: PathBuf::from("/dev/null"),
+        }
+    }
+
+    #[test]
+    fn upload_destination_is_quoted_literally() {
+        let sources = [Location::Local("/src/a [1].txt".to_string())];
+
+        let argv = upload_argv(&target("~/site [new]"), "css*", &sources);
+        assert_eq!(argv[argv.len() - 2], "/src/a [1].txt");
+        assert_eq!(argv[argv.len() - 1], "u@dev:~/'site [new]/css*'");
+
+        let argv = upload_argv(&target("/srv/it's/"), "", &sources);
+        assert_eq!(argv.last().unwrap(), "u@dev:'/srv/it'\\''s'");
+    }
+
+    #[test]
+    fn remote_join_handles_empty_parts() {
+        assert_eq!(remote_join("/srv/app/", "css"), "/srv/app/css");
+        assert_eq!(remote_join("/srv/app", ""), "/srv/app");
+        assert_eq!(remote_join("", "css"), "css");
+    }
+}
@@ -0,0 +1,458 @@
+// ネイティブSFTP転送の結合テスト
+//
+// テストごとにプロセス内でSSH/SFTPサーバー（russh・russh-sftp）を起動し、
+// 一時ディレクトリをリモートのホームディレクトリとして転送を確認します。
+// サーバーはコマンドの実行を拒否するため、検証はSFTPでの読み出しにフォールバックします。
+
+#![cfg(feature = "native")]
+
+use russh::keys::ssh_key::private::Ed25519Keypair;
+use russh::keys::ssh_key::LineEnding;
+use russh::keys::{PrivateKey, PublicKey};
+use russh::server::{Auth, Msg, Session};
+use russh::{Channel, ChannelId};
+use russh_sftp::protocol::{
+    Attrs, Data, File as SftpFile, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode,
+};
+use sshportal::native::{self, TransferOptions};
+use sshportal::{Endpoint, Error, Location, ProgressEvent};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// テスト用のSSHサーバー
+struct TestServer {
+    /// リモートのホームディレクトリ
+    root: TempDir,
+    /// クライアントの作業ディレクトリ（鍵とknown_hostsも置く）
+    local: TempDir,
+    /// 接続先
+    endpoint: Endpoint,
+}
+
+impl TestServer {
+    /// サーバーを起動し、接続できる鍵とknown_hostsを用意します
+    fn start() -> Self {
+        Self::start_with_host_key(&[7u8; 32])
+    }
+
+    /// known_hostsに記録するホスト鍵を指定してサーバーを起動します
+    fn start_with_host_key(known_seed: &[u8; 32]) -> Self {
+        let root = TempDir::new().unwrap();
+        let local = TempDir::new().unwrap();
+
+        let host_key = key_from_seed(&[7u8; 32]);
+        let client_key = key_from_seed(&[9u8; 32]);
+        let key_path = local.path().join("id_ed25519");
+        fs::write(&key_path, client_key.to_openssh(LineEnding::LF).unwrap().as_bytes()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let known_hosts = local.path().join("known_hosts");
+        let recorded = key_from_seed(known_seed).public_key().to_openssh().unwrap();
+        fs::write(&known_hosts, format!("[127.0.0.1]:{} {}\n", port, recorded)).unwrap();
+
+        let config = Arc::new(russh::server::Config {
+            keys: vec![host_key],
+            ..Default::default()
+        });
+        let allowed = client_key.public_key().clone();
+        let home = root.path().to_path_buf();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                loop {
+                    let (socket, _) = listener.accept().await.unwrap();
+                    let handler = SshHandler {
+                        allowed: allowed.clone(),
+                        home: home.clone(),
+                        channels: HashMap::new(),
+                    };
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        if let Ok(session) = russh::server::run_stream(config, socket, handler).await {
+                            let _ = session.await;
+                        }
+                    });
+                }
+            });
+        });
+
+        let endpoint = Endpoint::new("tester@127.0.0.1", port)
+            .with_key_path(key_path.to_string_lossy())
+            .with_known_hosts(known_hosts.to_string_lossy());
+        TestServer { root, local, endpoint }
+    }
+
+    /// リモート側（サーバーのホームディレクトリ）のパス
+    fn remote_file(&self, path: &str) -> PathBuf {
+        self.root.path().join(path)
+    }
+
+    /// ローカル側のパス
+    fn local_file(&self, path: &str) -> PathBuf {
+        self.local.path().join(path)
+    }
+
+    /// リモートのパス指定
+    fn remote(&self, path: &str) -> Location {
+        Location::Remote {
+            endpoint: self.endpoint.clone(),
+            path: path.to_string(),
+        }
+    }
+
+    /// ローカルのパス指定
+    fn local(&self, path: &str) -> Location {
+        Location::Local(self.local_file(path).to_string_lossy().to_string())
+    }
+}
+
+/// シードからed25519鍵を作成します
+fn key_from_seed(seed: &[u8; 32]) -> PrivateKey {
+    PrivateKey::from(Ed25519Keypair::from_seed(seed))
+}
+
+/// 転送中の進捗イベントを集計します
+#[derive(Default)]
+struct Recorded {
+    files: u64,
+    transferred: u64,
+    resumed: u64,
+}
+
+/// 進捗を記録しながらコピーします
+fn copy(sources: &[Location], dst: &Location, options: TransferOptions) -> (Result<(), Error>, Recorded) {
+    let mut recorded = Recorded::default();
+    let result = native::copy(sources, dst, &options, &mut |event| match event {
+        ProgressEvent::Transferred(n) => recorded.transferred += n,
+        ProgressEvent::Resumed(n) => recorded.resumed += n,
+        ProgressEvent::FileFinished => recorded.files += 1,
+        _ => {}
+    });
+    (result, recorded)
+}
+
+/// 再開と検証を有効にしたオプション
+fn resume_options() -> TransferOptions {
+    let mut options = TransferOptions::default();
+    options.resume = true;
+    options.verify = true;
+    options
+}
+
+#[test]
+fn upload_file_and_directory() {
+    let server = TestServer::start();
+    fs::write(server.local_file("a.txt"), "alpha").unwrap();
+    fs::create_dir_all(server.local_file("tree/sub")).unwrap();
+    fs::write(server.local_file("tree/one.txt"), "one").unwrap();
+    fs::write(server.local_file("tree/sub/two.txt"), "two").unwrap();
+    fs::create_dir(server.remote_file("dest")).unwrap();
+
+    let (result, recorded) = copy(
+        &[server.local("a.txt"), server.local("tree")],
+        &server.remote("~/dest"),
+        TransferOptions::default(),
+    );
+    result.unwrap();
+
+    assert_eq!(fs::read_to_string(server.remote_file("dest/a.txt")).unwrap(), "alpha");
+    assert_eq!(fs::read_to_string(server.remote_file("dest/tree/one.txt")).unwrap(), "one");
+    assert_eq!(fs::read_to_string(server.remote_file("dest/tree/sub/two.txt")).unwrap(), "two");
+    assert_eq!(recorded.files, 3);
+    assert_eq!(recorded.transferred, 11);
+}
+
+#[test]
+fn download_file_and_directory() {
+    let server = TestServer::start();
+    fs::create_dir_all(server.remote_file("logs/old")).unwrap();
+    fs::write(server.remote_file("logs/app.log"), "line\n").unwrap();
+    fs::write(server.remote_file("logs/old/app.log.1"), "older\n").unwrap();
+    fs::write(server.remote_file("notes.txt"), "notes").unwrap();
+
+    let (result, _) = copy(&[server.remote("logs")], &server.local("."), TransferOptions::default());
+    result.unwrap();
+    let (result, _) = copy(&[server.remote("notes.txt")], &server.local("renamed.txt"), TransferOptions::default());
+    result.unwrap();
+
+    assert_eq!(fs::read_to_string(server.local_file("logs/app.log")).unwrap(), "line\n");
+    assert_eq!(fs::read_to_string(server.local_file("logs/old/app.log.1")).unwrap(), "older\n");
+    assert_eq!(fs::read_to_string(server.local_file("renamed.txt")).unwrap(), "notes");
+}
+
+#[test]
+fn resume_upload_counts_only_written_bytes() {
+    let server = TestServer::start();
+    let content = "0123456789".repeat(1000);
+    fs::write(server.local_file("big.bin"), &content).unwrap();
+    fs::write(server.remote_file("big.bin"), &content[..4000]).unwrap();
+
+    let (result, recorded) = copy(&[server.local("big.bin")], &server.remote("big.bin"), resume_options());
+    result.unwrap();
+
+    assert_eq!(fs::read_to_string(server.remote_file("big.bin")).unwrap(), content);
+    assert_eq!(recorded.resumed, 4000);
+    assert_eq!(recorded.transferred, 6000);
+    assert_eq!(recorded.files, 1);
+}
+
+#[test]
+fn resume_download_counts_only_written_bytes() {
+    let server = TestServer::start();
+    let content = "abcdefghij".repeat(1000);
+    fs::write(server.remote_file("big.bin"), &content).unwrap();
+    fs::write(server.local_file("big.bin"), &content[..2500]).unwrap();
+
+    let (result, recorded) = copy(&[server.remote("big.bin")], &server.local("big.bin"), resume_options());
+    result.unwrap();
+
+    assert_eq!(fs::read_to_string(server.local_file("big.bin")).unwrap(), content);
+    assert_eq!(recorded.resumed, 2500);
+    assert_eq!(recorded.transferred, 7500);
+}
+
+#[test]
+fn resume_detects_corrupted_partial_file() {
+    let server = TestServer::start();
+    fs::write(server.local_file("data.txt"), "good content").unwrap();
+    fs::write(server.remote_file("data.txt"), "BAD!").unwrap();
+
+    let (result, recorded) = copy(&[server.local("data.txt")], &server.remote("data.txt"), resume_options());
+    assert!(matches!(result, Err(Error::ChecksumMismatch(ref files)) if files == &["data.txt".to_string()]));
+    // 検証に失敗したファイルは完了として報告しない
+    assert_eq!(recorded.files, 0);
+}
+
+#[test]
+fn remote_glob_is_rejected_before_connecting() {
+    let server = TestServer::start();
+    fs::write(server.remote_file("a.log"), "a").unwrap();
+
+    let (result, recorded) = copy(&[server.remote("*.log")], &server.local("."), TransferOptions::default());
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+    assert_eq!(recorded.files, 0);
+    assert!(!server.local_file("a.log").exists());
+}
+
+#[test]
+fn glob_characters_in_local_names_are_literal() {
+    let server = TestServer::start();
+    fs::write(server.local_file("[ab]*.txt"), "literal").unwrap();
+    fs::write(server.local_file("a.txt"), "other").unwrap();
+
+    let (result, recorded) = copy(&[server.local("[ab]*.txt")], &server.remote("~"), TransferOptions::default());
+    result.unwrap();
+
+    assert_eq!(fs::read_to_string(server.remote_file("[ab]*.txt")).unwrap(), "literal");
+    assert!(!server.remote_file("a.txt").exists());
+    assert_eq!(recorded.files, 1);
+}
+
+#[test]
+fn changed_host_key_is_rejected() {
+    let server = TestServer::start_with_host_key(&[8u8; 32]);
+    fs::write(server.local_file("a.txt"), "alpha").unwrap();
+
+    let (result, _) = copy(&[server.local("a.txt")], &server.remote("a.txt"), TransferOptions::default());
+    assert!(matches!(result, Err(Error::Transport(ref message)) if message.contains("known_hosts")));
+    assert!(!server.remote_file("a.txt").exists());
+}
+
+/// SSHセッションのハンドラ（公開鍵認証とSFTPサブシステムのみ）
+struct SshHandler {
+    allowed: PublicKey,
+    home: PathBuf,
+    channels: HashMap<ChannelId, Channel<Msg>>,
+}
+
+impl russh::server::Handler for SshHandler {
+    type Error = russh::Error;
+
+    async fn auth_publickey(&mut self, _user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        if *key == self.allowed {
+            Ok(Auth::Accept)
+        } else {
+            Ok(Auth::reject())
+        }
+    }
+
+    async fn channel_open_session(&mut self, channel: Channel<Msg>, _session: &mut Session) -> Result<bool, Self::Error> {
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
+
+    async fn exec_request(&mut self, channel: ChannelId, _data: &[u8], session: &mut Session) -> Result<(), Self::Error> {
+        // SFTP専用のサーバーとしてコマンドの実行を拒否する
+        session.channel_failure(channel)
+    }
+
+    async fn subsystem_request(&mut self, channel: ChannelId, name: &str, session: &mut Session) -> Result<(), Self::Error> {
+        match self.channels.remove(&channel) {
+            Some(stream) if name == "sftp" => {
+                session.channel_success(channel)?;
+                let handler = SftpHandler {
+                    home: self.home.clone(),
+                    handles: HashMap::new(),
+                    next: 0,
+                };
+                russh_sftp::server::run(stream.into_stream(), handler).await;
+                Ok(())
+            }
+            _ => session.channel_failure(channel),
+        }
+    }
+}
+
+/// 開いているファイルまたはディレクトリ
+enum OpenHandle {
+    File(fs::File),
+    /// ディレクトリの未送信のエントリ（送信済みの場合はNone）
+    Dir(Option<Vec<SftpFile>>),
+}
+
+/// ホームディレクトリ以下を公開するSFTPサーバー
+struct SftpHandler {
+    home: PathBuf,
+    handles: HashMap<String, OpenHandle>,
+    next: u64,
+}
+
+impl SftpHandler {
+    /// SFTPのパスをローカルのパスに変換します（絶対パスもホーム以下として扱う）
+    fn path(&self, path: &str) -> PathBuf {
+        self.home.join(path.trim_start_matches('/'))
+    }
+
+    /// ハンドルを登録します
+    fn insert(&mut self, id: u32, handle: OpenHandle) -> Handle {
+        self.next += 1;
+        let name = self.next.to_string();
+        self.handles.insert(name.clone(), handle);
+        Handle { id, handle: name }
+    }
+
+    /// 開いているファイルを返します
+    fn file(&mut self, handle: &str) -> Result<&mut fs::File, StatusCode> {
+        match self.handles.get_mut(handle) {
+            Some(OpenHandle::File(file)) => Ok(file),
+            _ => Err(StatusCode::Failure),
+        }
+    }
+}
+
+/// 成功を表すステータスを返します
+fn ok(id: u32) -> Status {
+    Status {
+        id,
+        status_code: StatusCode::Ok,
+        error_message: "Ok".to_string(),
+        language_tag: "en-US".to_string(),
+    }
+}
+
+/// 入出力エラーをSFTPのステータスに変換します
+fn status(e: std::io::Error) -> StatusCode {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => StatusCode::NoSuchFile,
+        std::io::ErrorKind::PermissionDenied => StatusCode::PermissionDenied,
+        _ => StatusCode::Failure,
+    }
+}
+
+impl russh_sftp::server::Handler for SftpHandler {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn open(&mut self, id: u32, filename: String, flags: OpenFlags, _attrs: FileAttributes) -> Result<Handle, Self::Error> {
+        let file = fs::OpenOptions::new()
+            .read(flags.contains(OpenFlags::READ))
+            .write(flags.contains(OpenFlags::WRITE))
+            .append(flags.contains(OpenFlags::APPEND))
+            .create(flags.contains(OpenFlags::CREATE))
+            .truncate(flags.contains(OpenFlags::TRUNCATE))
+            .open(self.path(&filename))
+            .map_err(status)?;
+        Ok(self.insert(id, OpenHandle::File(file)))
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        self.handles.remove(&handle);
+        Ok(ok(id))
+    }
+
+    async fn read(&mut self, id: u32, handle: String, offset: u64, len: u32) -> Result<Data, Self::Error> {
+        let file = self.file(&handle)?;
+        file.seek(SeekFrom::Start(offset)).map_err(status)?;
+        let mut data = vec![0u8; len as usize];
+        let n = file.read(&mut data).map_err(status)?;
+        if n == 0 {
+            return Err(StatusCode::Eof);
+        }
+        data.truncate(n);
+        Ok(Data { id, data })
+    }
+
+    async fn write(&mut self, id: u32, handle: String, offset: u64, data: Vec<u8>) -> Result<Status, Self::Error> {
+        let file = self.file(&handle)?;
+        file.seek(SeekFrom::Start(offset)).map_err(status)?;
+        file.write_all(&data).map_err(status)?;
+        Ok(ok(id))
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        let meta = fs::metadata(self.path(&path)).map_err(status)?;
+        Ok(Attrs { id, attrs: FileAttributes::from(&meta) })
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        let meta = fs::symlink_metadata(self.path(&path)).map_err(status)?;
+        Ok(Attrs { id, attrs: FileAttributes::from(&meta) })
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        let meta = self.file(&handle)?.metadata().map_err(status)?;
+        Ok(Attrs { id, attrs: FileAttributes::from(&meta) })
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(self.path(&path)).map_err(status)? {
+            let entry = entry.map_err(status)?;
+            let meta = entry.metadata().map_err(status)?;
+            files.push(SftpFile::new(entry.file_name().to_string_lossy(), FileAttributes::from(&meta)));
+        }
+        Ok(self.insert(id, OpenHandle::Dir(Some(files))))
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        match self.handles.get_mut(&handle) {
+            Some(OpenHandle::Dir(files)) => match files.take() {
+                Some(files) => Ok(Name { id, files }),
+                None => Err(StatusCode::Eof),
+            },
+            _ => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn mkdir(&mut self, id: u32, path: String, _attrs: FileAttributes) -> Result<Status, Self::Error> {
+        fs::create_dir(self.path(&path)).map_err(status)?;
+        Ok(ok(id))
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let real = Path::new("/").join(path.trim_start_matches('/'));
+        Ok(Name { id, files: vec![SftpFile::dummy(real.to_string_lossy())] })
+    }
+}